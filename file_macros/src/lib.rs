@@ -263,9 +263,10 @@ pub fn derive_struct_rw(input: TokenStream) -> TokenStream {
                                 let val = &nv.value;
                                 val_res = quote! {
                                     {
+                                        let pos = reader.stream_position()?;
                                         let magic = <#ty>::read(reader, ctx)?;
                                         if &magic != #val {
-                                            return Err("magic not equal".into())
+                                            return Err(Box::new(crate::reerr::ReError::magic(#val, &magic).at(pos)))
                                         }
                                         magic
                                     }
@@ -74,6 +74,9 @@ pub fn derive_instance(input: TokenStream) -> TokenStream {
                     fn as_any(&self) -> &dyn Any {
                         self
                     }
+                    fn as_any_mut(&mut self) -> &mut dyn Any {
+                        self
+                    }
                     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
                         serde_json::json!(self)
                     }
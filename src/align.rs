@@ -1,6 +1,6 @@
 use std::{io::{Read, Seek}, ops::*};
 
-use crate::{file::Result, reerr::FileParseError};
+use crate::{file::Result, reerr::{ErrorKind, ReError}};
 
 pub fn align_up<T: Copy + Add<Output = T> + Sub<Output = T> + Rem<Output = T>>(
     value: T,
@@ -14,7 +14,7 @@ pub fn seek_align_up<R: Read + Seek>(stream: &mut R, align: u64) -> Result<u64>
     let aligned = align_up(pos, align);
     if aligned != pos {
         let mut buf = vec![0; (aligned - pos).try_into()?];
-        stream.read_exact(&mut buf).map_err(|_f| Box::new(FileParseError::BadAlign(pos, align)))?;
+        stream.read_exact(&mut buf).map_err(|_f| Box::new(ReError::new(ErrorKind::BadAlign { align }).at(pos)))?;
     }
     Ok(aligned)
 }
@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::{game::GameProfile, pak::PakKey, reerr::Result};
+
+pub const DEFAULT_GAME: &str = "mhwilds";
+pub const DEFAULT_RSZ_FILE: &str = "rszmhwilds.json";
+pub const DEFAULT_ENUM_FILE: &str = "enums.json";
+pub const DEFAULT_OUT_DIR: &str = "outputs";
+
+/// Settings for one game install, every field is optional and falls back to the builtin defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Picks the table of file versions, one of the names in `version::GAMES`
+    pub game: Option<String>,
+    /// Usually the natives folder of the game
+    pub root_dir: Option<PathBuf>,
+    /// Directories or paks stacked above the root, files in later ones override the earlier ones
+    #[serde(default)]
+    pub overlays: Vec<PathBuf>,
+    pub rsz: Option<String>,
+    pub enums: Option<String>,
+    pub out_dir: Option<String>,
+    /// Keep the path inside the root directory in the output directory
+    pub preserve: Option<bool>,
+    pub steamid: Option<String>,
+    /// Path list used to find files in paks
+    pub pak_paths: Option<PathBuf>,
+    /// Rsa modulus and exponent of the pak entry table key, as hex
+    pub pak_modulus: Option<String>,
+    pub pak_exponent: Option<String>,
+}
+
+impl Profile {
+    /// Game profile with the schema files to use, the env vars win over the arguments, which win over the profile
+    pub fn game_profile(&self, rsz: Option<&str>, enums: Option<&str>) -> Result<GameProfile> {
+        let rsz_file = std::env::var("RSZ_FILE").ok()
+            .or(rsz.map(str::to_string))
+            .or(self.rsz.clone())
+            .unwrap_or(DEFAULT_RSZ_FILE.to_string());
+        if !Path::new(&rsz_file).exists() {
+            eprintln!("BIG WARNING: {} not found", rsz_file);
+        }
+
+        let enum_file = std::env::var("ENUM_FILE").ok()
+            .or(enums.map(str::to_string))
+            .or(self.enums.clone())
+            .unwrap_or(DEFAULT_ENUM_FILE.to_string());
+        if !Path::new(&enum_file).exists() {
+            eprintln!("BIG WARNING: {} not found", enum_file);
+        }
+        GameProfile::new(self.game.as_deref().unwrap_or(DEFAULT_GAME), &rsz_file, &enum_file)
+    }
+
+    /// Makes the game profile of the schema files the one every thread uses by default
+    pub fn set_schema_files(&self, rsz: Option<&str>, enums: Option<&str>) -> Result<()> {
+        self.game_profile(rsz, enums)?.set_default()
+    }
+
+    /// The root followed by the overlays
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.root_dir.iter().chain(&self.overlays).cloned().collect()
+    }
+
+    pub fn pak_key(&self) -> Result<Option<PakKey>> {
+        match (&self.pak_modulus, &self.pak_exponent) {
+            (Some(modulus), Some(exponent)) => Ok(Some(PakKey::from_hex(modulus, exponent)?)),
+            (None, None) => Ok(None),
+            _ => Err("The pak key needs both pak_modulus and pak_exponent".into()),
+        }
+    }
+
+    pub fn out_dir(&self, out_dir: Option<String>) -> String {
+        out_dir.or(self.out_dir.clone()).unwrap_or(DEFAULT_OUT_DIR.to_string())
+    }
+}
+
+/// Contents of `mhtame.toml`
+/// ```toml
+/// default = "wilds"
+///
+/// [profiles.wilds]
+/// game = "mhwilds"
+/// root_dir = "E:/MHWilds/re_chunk_000"
+/// overlays = ["E:/MHWilds/mods/natives"]
+/// rsz = "rszmhwilds.json"
+/// enums = "enums.json"
+/// out_dir = "outputs"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Profile used when none is given
+    pub default: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    pub const FILE_NAME: &'static str = "mhtame.toml";
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("Could not open config {path:?}: {e}"))?;
+        let config = toml::from_str(&data).map_err(|e| format!("Could not parse config {path:?}: {e}"))?;
+        Ok(config)
+    }
+
+    /// Loads the given config, or `mhtame.toml` in the working directory if there is one
+    pub fn find(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(Self::FILE_NAME).exists() => Self::load(Path::new(Self::FILE_NAME)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// The named profile, or the default one, or an empty profile if the config has no default
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let Some(name) = name.or(self.default.as_deref()) else {
+            return Ok(Profile::default())
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None => Err(format!("Profile {name} not found in config, available: {:?}", self.profiles.keys().collect::<Vec<_>>()).into()),
+        }
+    }
+}
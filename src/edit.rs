@@ -5,7 +5,7 @@ use fasthash::murmur3;
 use half::f16;
 use serde::Serialize;
 
-use crate::{rsz::{dump::{get_enum_list, get_enum_val, RszDump, RszField, RszStruct}, rszserde::{DeRsz, DeRszInstance, DeRszRegistry, DeRszType, Enummable, ExternObject, Guid, Nullable, Object, RszDeserializerCtx, RszFieldsValue, StringU16, Struct, StructData}}, save::{types::{Array, Class, FieldType}, SaveFile}, user::User};
+use crate::{rsz::{dump::{get_enum_list, get_enum_val, RszDump, RszField, RszStruct}, rszserde::{DeRsz, DeRszInstance, DeRszRegistry, DeRszType, Enummable, ExternObject, Guid, Nullable, Object, RawInstance, RszDeserializerCtx, RszFieldsValue, StringU16, Struct, StructData}}, save::{types::{Array, Class, FieldType}, SaveFile}};
 
 pub type EditableFile = dyn Edit;
 
@@ -246,8 +246,15 @@ impl Edit for Object {
     }
 }
 impl Edit for ExternObject {
-    fn edit(&mut self, ui: &mut Ui, ctx: &mut C) {
-        todo!()
+    fn edit(&mut self, ui: &mut Ui, _ctx: &mut C) {
+        // the object lives in another user file, only show where
+        ui.label(format!("extern {}", self.path()));
+    }
+}
+
+impl Edit for RawInstance {
+    fn edit(&mut self, ui: &mut Ui, _ctx: &mut C) {
+        ui.label(format!("unknown type {:08x}, {} bytes", self.hash, self.data.len()));
     }
 }
 ///AAAAAAAAAAAAAHHHHHHHHHHHHHHHHHHHHHHHHHHHHHHHHHH AIDK
@@ -416,6 +423,9 @@ impl Edit for StructData {
             type_descriptors: &fake_types,
             cur_hash,
             field,
+            field_offsets: Vec::new(),
+            lenient: false,
+            padding: 0,
         };
         if let Ok(dersz_fn) = dersz_fn {
             let mut x: Box<dyn DeRszInstance> = dersz_fn(&mut de_ctx).unwrap();
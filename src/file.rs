@@ -1,13 +1,9 @@
-use std::{collections::HashSet, error::Error, fs::File, io::{Cursor, Read, Seek, Write}, mem::MaybeUninit, path::{Path, PathBuf}, time::SystemTime};
+use std::{collections::BTreeSet, error::Error, fs::File, io::{Cursor, Read, Seek, Write}, mem::MaybeUninit, path::{Path, PathBuf}, time::Instant};
 
-use crate::{file_ext::SeekExt, font::Oft, gensdk::Sdk, msg::Msg, pog::{Pog, PogList}, rsz::rszserde::{DeRsz, Guid, StringU16}, save::{types::to_dersz, SaveContext}, scn::Scn, tdb::TDBHeader, tex::Tex, user::User};
-use crate::save::SaveFile;
+use crate::{file_ext::SeekExt, format::FileFormat, game::GameProfile, gensdk::Sdk, manifest::{Manifest, ManifestEntry, SchemaStamp}, pak::{Compression, PakWriter}, reerr::{ErrorKind, ResultExt}, report::{FileReport, Report}, resource::{build_from_json, streaming_path, LoadOptions, OutputStyle, Resource, Source}, rsz::{rszserde::{Guid, StringU16}, Rsz}, save::parse_steamid, search::{Query, SearchMatch, SearchReport}, verify::{verify_user, Mismatch, VerifyReport}, version::VersionEntry, vfs::{vfs_path, DirVfs, Vfs}};
 use serde::Serialize;
 
-use crate::pog::{PogNode, PogPoint};
-
 use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
 
 #[macro_export]
 macro_rules! sread {
@@ -17,24 +13,25 @@ macro_rules! sread {
 }
 
 pub trait Dump<R = ()> {
-    fn dump(file_path: &Path, output_path: &Path) -> Result<R>;
+    fn dump(file_path: &Path, output_path: &Path, style: OutputStyle) -> Result<R>;
 }
 
 pub trait DefaultDump {}
 
 impl<T: StructRW + DefaultDump + Serialize> Dump for T {
-    fn dump(file_path: &Path, output_path: &Path) -> Result<()> {
+    fn dump(file_path: &Path, output_path: &Path, style: OutputStyle) -> Result<()> {
         let mut file = File::open(file_path)?;
         let mut buf = vec![];
         file.read_to_end(&mut buf)?;
         let mut reader = Cursor::new(buf);
         let res = T::read(&mut reader, &mut ())?;
         let mut output_path = output_path.to_path_buf();
-        output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-        let json_res = serde_json::to_string_pretty(&res)?;
+        let suffix = if style == OutputStyle::Ndjson { ".ndjson" } else { ".json" };
+        output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + suffix);
         std::fs::create_dir_all(output_path.parent().unwrap())?;
-        let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-        f.write_all(json_res.as_bytes())?;
+        let mut f = std::io::BufWriter::new(std::fs::File::create(&output_path)?);
+        style.write(&mut f, &res)?;
+        f.flush()?;
         Ok(())
     }
 }
@@ -215,15 +212,59 @@ impl<C> StructRW<C> for StringOffset {
   }
   */
 
+#[derive(Debug, Serialize)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub format: FileFormat,
+    pub size: u64,
+    pub details: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for FileInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.path.display())?;
+        writeln!(f, "    format: {}", self.format)?;
+        write!(f, "    size: {}", self.size)?;
+        for (name, value) in &self.details {
+            write!(f, "\n    {name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What dumping a single file produced
+#[derive(Debug)]
+pub struct DumpOutput {
+    pub format: FileFormat,
+    pub outputs: Vec<PathBuf>,
+    pub types: BTreeSet<u32>,
+}
+
+/// Result of one file in a batch, everything in it is Send
+struct FileResult {
+    report: FileReport,
+    types: BTreeSet<u32>,
+    entry: Option<ManifestEntry>,
+}
+
 pub struct FileReader {
     dump_sdk: bool,
-    sdk_types: HashSet<u32>,
+    incremental: bool,
+    output_style: OutputStyle,
+    sdk_types: BTreeSet<u32>,
     steamid: Option<String>,
+    lenient: bool,
+    /// Progress goes to stderr, for when stdout carries a report
+    log_stderr: bool,
+    /// Lets build replace files in the input directory
+    overwrite: bool,
 
     #[allow(unused)]
     dump_all_rsz: bool,
     output_dir: std::path::PathBuf,
     root_dir: Option<std::path::PathBuf>,
+    /// Where files are read from, the root directory unless another one is set
+    vfs: Box<dyn Vfs>,
     keep_path_structure: bool
 }
 
@@ -231,16 +272,69 @@ impl FileReader {
     pub fn new(output_dir: std::path::PathBuf, root_dir: Option<std::path::PathBuf>, dump_sdk: bool, dump_all_rsz: bool, keep_path_structure: bool, steamid: Option<String>) -> FileReader {
         Self {
             dump_sdk,
+            incremental: false,
+            output_style: OutputStyle::default(),
             output_dir,
             dump_all_rsz,
-            sdk_types: HashSet::new(),
+            sdk_types: BTreeSet::new(),
+            vfs: Box::new(DirVfs::new(root_dir.clone().unwrap_or_default())),
             root_dir,
             steamid,
+            lenient: false,
+            log_stderr: false,
+            overwrite: false,
             keep_path_structure,
         }
 
     }
 
+    /// Keep a manifest in the output directory and skip files that did not change since the last dump
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+    }
+
+    pub fn set_output_style(&mut self, output_style: OutputStyle) {
+        self.output_style = output_style;
+    }
+
+    /// Keep instances of types missing from the rsz dump as raw bytes instead of failing the file
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Lets build write over files in the input directory, like the game file a dump was made from
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+
+    pub fn set_log_stderr(&mut self, log_stderr: bool) {
+        self.log_stderr = log_stderr;
+    }
+
+    fn info(&self, message: std::fmt::Arguments) {
+        if self.log_stderr {
+            eprintln!("[INFO] {message}");
+        } else {
+            println!("[INFO] {message}");
+        }
+    }
+
+    pub fn set_vfs(&mut self, vfs: Box<dyn Vfs>) {
+        self.vfs = vfs;
+    }
+
+    pub fn vfs(&self) -> &dyn Vfs {
+        self.vfs.as_ref()
+    }
+
+    /// Where the file is read from, the entry when it is inside a pak
+    fn read(&self, file: &std::path::Path) -> Result<Vec<u8>> {
+        match Source::vfs(self.vfs.as_ref(), &vfs_path(file))? {
+            Source::Bytes { data, .. } => Ok(data),
+            Source::Path(path) => Ok(std::fs::read(path)?),
+        }
+    }
+
     pub fn get_full_file_path(&self, file_path: &std::path::Path) -> std::path::PathBuf {
         match self.root_dir {
             Some(ref root_dir) => root_dir.join(&file_path),
@@ -266,348 +360,358 @@ impl FileReader {
         self.output_dir.join(path)
     }
 
-    pub fn dump_file(&mut self, file: std::path::PathBuf) -> Result<()> {
-        let file_path = self.get_full_file_path(&file);
-        let output_path = self.get_output_path(&file);
-        println!("[INFO] Dumping File: {file_path:?}");
+    /// Dumps a single file, the rsz types it uses are returned instead of stored so files can be dumped in parallel
+    pub fn dump_file(&self, file: &std::path::Path) -> Result<DumpOutput> {
+        self.dump_file_inner(file).in_file(&self.get_full_file_path(file))
+    }
+
+    fn dump_file_inner(&self, file: &std::path::Path) -> Result<DumpOutput> {
+        let file_path = self.get_full_file_path(file);
+        let output_path = self.get_output_path(file);
+        self.info(format_args!("Dumping File: {file_path:?}"));
 
         let file_name = file_path.file_name().ok_or(format!("Path does not contain file"))?.to_string_lossy();
 
-        let split = file_name.strip_suffix(".json").unwrap_or(&file_name).split('.').collect::<Vec<_>>();
-        let is_json = file_name.ends_with(".json");
-        let file_ext = *split.get(1).ok_or(format!("Could not determine file type from file name"))?;
+        if file_name.ends_with(".json") {
+            return Err(format!("{file_name} is a json file, use the build command to recreate it").into())
+        }
+        let resource = Resource::load_from_vfs(self.vfs.as_ref(), &vfs_path(file), &self.load_options()?)?;
+        let outputs = resource.export(&output_path, self.output_style)?;
+        for output_path in &outputs {
+            self.info(format_args!("Saved File {:?}", output_path));
+        }
+        Ok(DumpOutput { format: resource.format(), outputs, types: resource.types() })
+    }
+
+    fn load_options(&self) -> Result<LoadOptions> {
+        Ok(LoadOptions {
+            steamid: self.steamid.as_deref().map(parse_steamid).transpose()?,
+            lenient: self.lenient,
+        })
+    }
 
-        match file_ext {
-            "msg" => {
-                Msg::dump(&file_path, &output_path)?;
-            },
-            "poglst" => {
-                PogList::dump(&file_path, &output_path)?;
+
+    /// Dumps every file in the list in parallel, the report has one entry per file in the same order.
+    /// When incremental, files that did not change since the last run are skipped
+    pub fn dump_files(&mut self, file_list: Vec<std::path::PathBuf>) -> Result<Report> {
+        let start = Instant::now();
+        let mut manifest = if self.incremental {
+            let mut manifest = Manifest::load(&self.output_dir)?;
+            let stale = manifest.set_schema(SchemaStamp::current()?);
+            if stale > 0 {
+                self.info(format_args!("Schema files changed, {stale} rsz outputs are stale"));
             }
-            "bin" => {
-                if let Some(steamid) = &self.steamid {
-                    let steamid = if let Some(hex) = steamid.strip_prefix("0x") {
-                        u64::from_str_radix(hex, 16)
-                    } else {
-                        u64::from_str_radix(steamid, 10)
-                    }?;
-                    //Mandarin::sanity_check(&file_path);
-                    let mut reader = File::open(&file)?;
-                    let save = SaveFile::read(&mut reader, &mut SaveContext{key: steamid})?;
-                    //let save = SaveFile::from_file(&file)?;
-                    let dersz = to_dersz(save.data)?;
-                    //println!("{:?}, {:?}", dersz.structs.len(), dersz.roots);
-                    let mut output_path = output_path.clone();
-                    output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".json");
-                    //println!("{output_path:?}");
-                    std::fs::create_dir_all(output_path.parent().unwrap())?;
-                    let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                    let json = serde_json::to_string_pretty(&dersz)?;
-                    f.write_all(json.as_bytes())?;
-                } else {
-
-                    // try to brute force
-                    let mut reader = File::open(&file).unwrap();
-                    let mut buf = vec![];
-                    reader.read_to_end(&mut buf).unwrap();
-                    let mut reader = Cursor::new(&buf);
-                    let _ = SaveFile::read(&mut reader, &mut SaveContext{key: 0});
-                    /*(0..c).into_par_iter().for_each(|i| {
-                        let key = 0x0110000100000000 + i;
-                        let mut reader = Cursor::new(&buf);
-                        let save = match SaveFile::read(&mut reader, &mut SaveContext{key}) {
-                            Ok(save) => {
-                                println!("decrypted with key={:#18x}", key);
-                                //let save = SaveFile::from_file(&file)?;
-                                let dersz = DeRsz::from(save);
-                                //println!("{:?}, {:?}", dersz.structs.len(), dersz.roots);
-                                let mut output_path = output_path.clone();
-                                output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".json");
-                                let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                                let json = serde_json::to_string_pretty(&dersz).unwrap();
-                                f.write_all(json.as_bytes()).unwrap();
-                            },
-                            Err(_) => { }
-                        };
-                    });*/
-                    return Err(format!("Can only decrypt save files with steamid.\nGo here to find it https://help.steampowered.com/en/faqs/view/2816-BE67-5B69-0FEC").into())
+            Some(manifest)
+        } else {
+            None
+        };
+        // collect keeps the input order so merging and error output do not depend on scheduling,
+        // the error is turned into the report entry here since Box<dyn Error> is not Send
+        let game = GameProfile::active();
+        let results: Vec<_> = file_list.par_iter()
+            .map(|file| game.enter(|| self.dump_file_incremental(file, manifest.as_ref())))
+            .collect();
+        let mut report = Report::default();
+        for result in results {
+            if let Some(e) = &result.report.error {
+                eprintln!("[ERROR] Error dumping file {:?}: {e}", result.report.path);
+            }
+            if let Some(manifest) = &mut manifest {
+                match result.entry {
+                    Some(entry) => manifest.insert(&result.report.path, entry),
+                    None => manifest.remove(&result.report.path),
                 }
             }
-            "exe" => {
-                let mut file = File::open(&file_path)?;
-                TDBHeader::from_exe(&mut file)?;
+            self.sdk_types.extend(result.types);
+            report.files.push(result.report);
+        }
+        if let Some(manifest) = &manifest {
+            manifest.save(&self.output_dir)?;
+        }
+        if self.dump_sdk {
+            let mut sdk = Sdk::new();
+            sdk.add_types(self.sdk_types.clone())?;
+            sdk.write_files();
+        }
+        report.duration = start.elapsed();
+        Ok(report)
+    }
 
-            }
-            "user" => {
-                if !is_json {
-                    let mut file = File::open(&file_path)?;
-                    //let user_test = UserTest::read(&mut file, &mut ());
-                    //println!("{:?}", user_test);
-                    file.seek(std::io::SeekFrom::Start(0))?;
-                    let user = User::new(file)?;
-                    self.sdk_types.extend(user.rsz.type_descriptors.iter().map(|t| t.hash));
-                    let mut output_path = output_path.clone();
-                    output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".json");
-                    let json = serde_json::to_string_pretty(&user)?;
-                    std::fs::create_dir_all(output_path.parent().unwrap())?;
-                    let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                    f.write_all(json.as_bytes())?;
-                } else {
-                    let mut output_path = output_path.clone();
-                    output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".custom");
-                    let user = User::from_json_file(&file_path.to_str().unwrap())?;
-                    std::fs::create_dir_all(output_path.parent().unwrap())?;
-                    let mut output = File::create(&output_path)?;
-                    output.write_all(&user.to_buf()?)?;
+    fn dump_file_incremental(&self, file: &std::path::Path, manifest: Option<&Manifest>) -> FileResult {
+        let file_start = Instant::now();
+        // files inside paks have no size and mtime of their own to compare, so they skip the manifest
+        let disk_path = self.vfs.disk_path(&vfs_path(file));
+        let manifest = manifest.filter(|_| disk_path.is_some());
+        let file_path = disk_path.unwrap_or_else(|| self.get_full_file_path(file));
+        let streaming = streaming_path(&vfs_path(file))
+            .and_then(|path| self.vfs.disk_path(&path))
+            .filter(|path| path.is_file());
+        if let Some(manifest) = manifest {
+            match manifest.fresh_entry(&file_path, streaming.as_deref(), self.output_style) {
+                Ok(Some(entry)) => {
+                    self.info(format_args!("Skipping Unchanged File: {file_path:?}"));
+                    return FileResult {
+                        report: FileReport::skipped(file_path, entry.format, entry.outputs.clone(), file_start.elapsed()),
+                        types: entry.types.iter().copied().collect(),
+                        entry: Some(entry),
+                    }
                 }
+                Ok(None) => {}
+                Err(e) => eprintln!("[ERROR] Could not check {file_path:?} against the manifest: {e}"),
             }
-            "tex" => {
-                let mut file = File::open(file_path.clone())?;
-                //let mut buf = vec![];
-                //file.read_to_end(&mut buf)?;
-                //let mut reader = Cursor::new(buf);
-                let tex = Tex::new(&mut file)?;
-                let rgba = tex.to_rgba(0, 0)?;
-                //println!("{}", rgba.data.len());
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".png");
-                std::fs::create_dir_all(output_path.parent().unwrap())?;
-                image::save_buffer(
-                    &output_path,
-                    &rgba.data,
-                    rgba.width,
-                    rgba.height,
-                    image::ExtendedColorType::Rgba8,
-                )?;
-            }
-            "scn" => {
-                let file = File::open(file_path.clone())?;
-                let rsz = Box::new(Scn::new(file)?.rsz);
-                let nodes = rsz.deserialize_to_dersz()?;
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-                let json_res = serde_json::to_string_pretty(&nodes)?; 
-                let _ = std::fs::create_dir_all(output_path.parent().unwrap())?;
-                let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                f.write_all(json_res.as_bytes())?;
-            }
-            "pog" => {
-                let file = File::open(file_path.clone())?;
-                let pog = Pog::new(file)?;
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-                let mut nodes = Vec::new();
-                for rsz in pog.rszs {
-                    self.sdk_types.extend(rsz.type_descriptors.iter().map(|t| t.hash));
-                    nodes.push(rsz.deserialize_to_dersz()?);
+        }
+        match self.dump_file(file) {
+            Ok(output) => {
+                let entry = manifest.and_then(|_| {
+                    ManifestEntry::new(&file_path, streaming.as_deref(), output.format, self.output_style, output.outputs.clone(), output.types.iter().copied().collect())
+                        .inspect_err(|e| eprintln!("[ERROR] Could not add {file_path:?} to the manifest: {e}"))
+                        .ok()
+                });
+                FileResult {
+                    report: FileReport::ok(file_path, output.format, output.outputs, file_start.elapsed()),
+                    types: output.types,
+                    entry,
                 }
-                #[derive(Serialize)]
-                struct Wrapped {
-                    points: Vec<PogPoint>,
-                    graph: Vec<PogNode>,
-                    nodes: Vec<DeRsz>,// confusing
+            }
+            Err(e) => {
+                let format = FileFormat::detect_file(&file_path).ok();
+                FileResult {
+                    report: FileReport::failed(file_path, format, e.as_ref(), file_start.elapsed()),
+                    types: BTreeSet::new(),
+                    entry: None,
                 }
-
-                let json_res = serde_json::to_string_pretty(&Wrapped {
-                    points: pog.points,
-                    graph: pog.nodes,
-                    nodes: nodes
-                })?; 
-
-                std::fs::create_dir_all(output_path.parent().unwrap())?;
-                let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                f.write_all(json_res.as_bytes())?;
-            },
-            "oft" => {
-                let file = File::open(file_path.clone())?;
-                let oft = Oft::new(file)?;
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".otf");
-                std::fs::create_dir_all(output_path.parent().unwrap())?;
-                let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                f.write(&oft.data)?;
             }
-            _ => { }
         }
+    }
 
-        println!("[INFO] Saved File {:?}", output_path);
-        Ok(())
+    /// Recreates a binary file from its json dump, only user files are supported so far
+    pub fn build_file(&self, file: &std::path::Path) -> Result<PathBuf> {
+        self.build_file_inner(file).in_file(&self.get_full_file_path(file))
     }
 
+    fn build_file_inner(&self, file: &std::path::Path) -> Result<PathBuf> {
+        let file_path = self.get_full_file_path(file);
+        let output_path = self.get_output_path(file);
+        self.info(format_args!("Building File: {file_path:?}"));
 
-    pub fn dump_files(&mut self, file_list: Vec<std::path::PathBuf>) -> Result<()> {
-        file_list.iter().for_each(|file| {
-            match self.dump_file(PathBuf::from(&file)) {
-                Ok(_) => { 
-                }
-                Err(e) => {
-                    eprintln!("[ERROR] Error dumping file {:?}: {e}", self.get_full_file_path(file));
-                }
-            };
+        let file_name = file_path.file_name().ok_or(format!("Path does not contain file"))?.to_string_lossy();
+        let stem = file_name.strip_suffix(".json").ok_or(format!("{file_name} is not a json file"))?;
+        let format = FileFormat::from_file_name(stem).ok_or(format!("Could not determine file type from file name {stem}"))?;
 
-        });
-        if self.dump_sdk {
-            let mut sdk = Sdk::new();
-            sdk.add_types(self.sdk_types.clone())?;
-            sdk.write_files();
+        // json dumps named without the version get the one the format is written with
+        let suffix = match (FileFormat::version_from_file_name(stem), VersionEntry::write_suffix(format)) {
+            (None, Some(version)) => format!(".{version}"),
+            _ => String::new(),
+        };
+        let mut output_path = output_path.clone();
+        output_path.set_file_name(format!("{stem}{suffix}"));
+        if !self.overwrite && self.is_input(&file_path, &output_path) {
+            return Err(format!("{output_path:?} already exists in the input directory, use --force to overwrite it").into())
         }
-        Ok(())
+        let buf = build_from_json(&String::from_utf8(self.read(file)?)?, format)?;
+        std::fs::create_dir_all(output_path.parent().unwrap())?;
+        File::create(&output_path)?.write_all(&buf)?;
+        self.info(format_args!("Saved File {:?}", output_path));
+        Ok(output_path)
     }
 
-    pub fn dump_filesv2(&mut self, file_list: Vec<std::path::PathBuf>) -> Result<()> {
-        let this = Arc::new(Mutex::new(self));
-        file_list.par_iter().for_each(|file| {
-            if let Err(e) = dump_file_simple(file, this.clone()) {
-                eprintln!("[ERROR] Error dumping file {:?}: {e}", file);
-            }
-        });
-        Ok(())
+    /// Whether the output already exists in the input directory, building with `-o` set to the root would replace the game file
+    fn is_input(&self, file_path: &Path, output_path: &Path) -> bool {
+        let Ok(output_path) = output_path.canonicalize() else {
+            return false
+        };
+        let input_dir = match &self.root_dir {
+            Some(root_dir) => root_dir.as_path(),
+            None => file_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")),
+        };
+        input_dir.canonicalize().is_ok_and(|input_dir| output_path.starts_with(input_dir))
     }
 
-}
-
-pub fn dump_file_simple<'a>(file: &std::path::Path, ctx: Arc<Mutex<&'a mut FileReader>>) -> Result<()> {
-    let (file_path, output_path) = {
-        let ctx = ctx.lock().unwrap();
-        (ctx.get_full_file_path(&file), ctx.get_output_path(&file))
-    };
-
-    println!("[INFO] Dumping File: {file_path:?}");
+    /// Builds every file in the list, returns the written files and the number of files that failed
+    pub fn build_files(&self, file_list: Vec<std::path::PathBuf>) -> (Vec<PathBuf>, usize) {
+        let game = GameProfile::active();
+        let results: Vec<_> = file_list.par_iter()
+            .map(|file| game.enter(|| self.build_file(file).map_err(|e| e.to_string())))
+            .collect();
+        let mut outputs = Vec::new();
+        let mut failed = 0;
+        for (file, result) in file_list.iter().zip(results) {
+            match result {
+                Ok(output) => outputs.push(output),
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("[ERROR] Error building file {:?}: {e}", self.get_full_file_path(file));
+                }
+            }
+        }
+        (outputs, failed)
+    }
 
-    let file_name = file_path.file_name().ok_or(format!("Path does not contain file"))?.to_string_lossy();
+    /// Dumps and rebuilds every user file in the list and compares the result with the original
+    pub fn verify_files(&self, file_list: &[PathBuf], style: OutputStyle) -> VerifyReport {
+        let game = GameProfile::active();
+        let files = file_list.par_iter()
+            .filter_map(|file| {
+                let result = game.enter(|| self.verify_file(file, style)).map_err(|e| e.to_string()).transpose()?;
+                Some((self.get_full_file_path(file), result))
+            })
+            .collect();
+        VerifyReport { files }
+    }
 
-    let split = file_name.strip_suffix(".json").unwrap_or(&file_name).split('.').collect::<Vec<_>>();
-    let is_json = file_name.ends_with(".json");
-    let file_ext = *split.get(1).ok_or(format!("Could not determine file type from file name"))?;
+    /// None if the file is not a user file
+    fn verify_file(&self, file: &Path, style: OutputStyle) -> Result<Option<Option<Mismatch>>> {
+        let Some((data, _)) = self.read_detected(file, |format| format == FileFormat::User)? else {
+            return Ok(None)
+        };
+        verify_user(&data, style).map(Some)
+    }
 
-    match file_ext {
+    /// Looks for objects matching the query in every file with rsz data
+    pub fn search_files(&self, file_list: &[PathBuf], query: &Query) -> SearchReport {
+        let game = GameProfile::active();
+        let files = file_list.par_iter()
+            .filter_map(|file| {
+                let result = game.enter(|| self.search_file(file, query)).map_err(|e| e.to_string()).transpose()?;
+                Some((self.get_full_file_path(file), result))
+            })
+            .collect();
+        SearchReport { files }
+    }
 
-        "exe" => {
-            let mut file = File::open(&file_path)?;
-            TDBHeader::from_exe(&mut file)?;
+    /// None if the file has no rsz data
+    fn search_file(&self, file: &Path, query: &Query) -> Result<Option<Vec<SearchMatch>>> {
+        let file_path = self.get_full_file_path(file);
+        let Some((data, format)) = self.read_detected(file, |format| format.has_rsz())? else {
+            return Ok(None)
+        };
+        let resource = Resource::read_format(Cursor::new(data), format, &self.load_options()?).in_file(&file_path)?;
+        query.search(&resource).in_file(&file_path).map(Some)
+    }
 
+    /// Reads the file if its format, found from the data so renamed files are included, is accepted.
+    /// Json dumps and files of unknown format give None
+    fn read_detected(&self, file: &Path, accept: impl Fn(FileFormat) -> bool) -> Result<Option<(Vec<u8>, FileFormat)>> {
+        let file_path = self.get_full_file_path(file);
+        let name = file_path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        if name.ends_with(".json") {
+            return Ok(None)
         }
-        "msg" => {
-            let mut file = File::open(&file_path)?;
-            let mut buf = vec![];
-            file.read_to_end(&mut buf)?;
-            let mut reader = Cursor::new(buf);
-            let msg = Msg::read(&mut reader, &mut ()).unwrap();
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".json");
-            let json = serde_json::to_string_pretty(&msg)?;
-            std::fs::create_dir_all(output_path.parent().unwrap())?;
-            let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-            f.write_all(json.as_bytes())?;
+        let data = self.read(file).in_file(&file_path)?;
+        match FileFormat::detect(&mut Cursor::new(&data), &name) {
+            Ok(format) if accept(format) => Ok(Some((data, format))),
+            _ => Ok(None),
         }
-        "user" => {
-            if !is_json {
-                let mut file = File::open(&file_path)?;
-                //let user_test = UserTest::read(&mut file, &mut ());
-                //println!("{:?}", user_test);
-                file.seek(std::io::SeekFrom::Start(0))?;
-                let user = User::new(file)?;
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".json");
-                let json = serde_json::to_string_pretty(&user)?;
-                std::fs::create_dir_all(output_path.parent().unwrap())?;
-                let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-                f.write_all(json.as_bytes())?;
-                let mut ctx = ctx.lock().unwrap();
-                ctx.sdk_types.extend(user.rsz.type_descriptors.iter().map(|t| t.hash));
-            } else {
-                let mut output_path = output_path.clone();
-                output_path.set_file_name(output_path.file_name().unwrap().to_string_lossy().to_string() + ".custom");
-                let user = User::from_json_file(&file_path.to_str().unwrap())?;
-                std::fs::create_dir_all(output_path.parent().unwrap())?;
-                let mut output = File::create(&output_path)?;
-                output.write_all(&user.to_buf()?)?;
-            }
-        }
-        "tex" => {
-            let mut file = File::open(file_path.clone())?;
-            //let mut buf = vec![];
-            //file.read_to_end(&mut buf)?;
-            //let mut reader = Cursor::new(buf);
-            let tex = Tex::new(&mut file)?;
-            let rgba = tex.to_rgba(0, 0)?;
-            //println!("{}", rgba.data.len());
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".png");
-            std::fs::create_dir_all(output_path.parent().unwrap())?;
-            image::save_buffer(
-                &output_path,
-                &rgba.data,
-                rgba.width,
-                rgba.height,
-                image::ExtendedColorType::Rgba8,
-            )?;
+    }
+
+    /// Packs files into a pak under the same path they have in the vfs
+    pub fn pack_files(vfs: &dyn Vfs, files: &[String], output: &Path, compression: Compression) -> Result<()> {
+        let mut writer = PakWriter::new(compression);
+        for file in files {
+            writer.add(file, vfs.read(file)?);
         }
-        "scn" => {
-            let file = File::open(file_path.clone())?;
-            let rsz = Box::new(Scn::new(file)?.rsz);
-            let nodes = rsz.deserialize_to_dersz()?;
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-            let json_res = serde_json::to_string_pretty(&nodes)?; 
-            let _ = std::fs::create_dir_all(output_path.parent().unwrap())?;
-            let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-            f.write_all(json_res.as_bytes())?;
+        writer.save(output)?;
+        println!("[INFO] Saved Pak {:?} with {} files", output, writer.len());
+        Ok(())
+    }
+
+    /// Reads the rsz type hashes of a file without dumping it
+    pub fn read_types(&self, file: &std::path::Path) -> Result<BTreeSet<u32>> {
+        let file_path = self.get_full_file_path(file);
+        let mut reader = Cursor::new(self.read(file).in_file(&file_path)?);
+        let name = file_path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        let format = FileFormat::detect(&mut reader, &name).in_file(&file_path)?;
+        if !format.has_rsz() {
+            return Err(ErrorKind::Unsupported(format!("{format} files do not contain rsz data")).into())
         }
-        "pog" => {
-            let file = File::open(file_path.clone())?;
-            let pog = Pog::new(file)?;
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-            let mut nodes = Vec::new();
-            {
-                let mut ctx = ctx.lock().unwrap();
-                for rsz in pog.rszs {
-                    ctx.sdk_types.extend(rsz.type_descriptors.iter().map(|t| t.hash));
-                    nodes.push(rsz.deserialize_to_dersz()?);
+        Ok(Resource::read_format(reader, format, &LoadOptions::default()).in_file(&file_path)?.types())
+    }
+
+    /// Collects the type hashes used by all files, returns the hashes and the number of files that failed
+    pub fn collect_types(&mut self, file_list: &[std::path::PathBuf]) -> (BTreeSet<u32>, usize) {
+        let game = GameProfile::active();
+        let results: Vec<_> = file_list.par_iter()
+            .map(|file| game.enter(|| self.read_types(file).map_err(|e| e.to_string())))
+            .collect();
+        let mut failed = 0;
+        let mut types = BTreeSet::new();
+        for (file, result) in file_list.iter().zip(results) {
+            match result {
+                Ok(file_types) => types.extend(file_types),
+                Err(e) => {
+                    failed += 1;
+                    eprintln!("[ERROR] Error reading types from {:?}: {e}", self.get_full_file_path(file));
                 }
             }
-            #[derive(Serialize)]
-            struct Wrapped {
-                points: Vec<PogPoint>,
-                graph: Vec<PogNode>,
-                nodes: Vec<DeRsz>,// confusing
-            }
+        }
+        self.sdk_types.extend(types.iter().copied());
+        (types, failed)
+    }
+
+    /// Generates the rust sdk for every type used by the files, returns the number of files that failed
+    pub fn generate_sdk(&mut self, file_list: &[std::path::PathBuf]) -> Result<usize> {
+        let (_, failed) = self.collect_types(file_list);
+        let mut sdk = Sdk::new();
+        sdk.add_types(self.sdk_types.clone())?;
+        sdk.write_files();
+        Ok(failed)
+    }
 
-            let json_res = serde_json::to_string_pretty(&Wrapped {
-                points: pog.points,
-                graph: pog.nodes,
-                nodes: nodes
-            })?; 
-
-            std::fs::create_dir_all(output_path.parent().unwrap())?;
-            let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-            f.write_all(json_res.as_bytes())?;
-        },
-        "poglst" => {
-            let mut file = File::open(&file_path)?;
-            let poglst = PogList::read(&mut file, &mut ())?;
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".json");
-            let json_res = serde_json::to_string_pretty(&poglst)?;
-            std::fs::create_dir_all(output_path.parent().unwrap())?;
-            let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-            f.write_all(json_res.as_bytes())?;
+    /// Reads the header of a file and describes what is in it
+    pub fn info_file(&self, file: &std::path::Path) -> Result<FileInfo> {
+        let file_path = self.get_full_file_path(file);
+        let data = self.read(file).in_file(&file_path)?;
+        let size = data.len() as u64;
+        let mut reader = Cursor::new(data);
+        let name = file_path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        let format = FileFormat::detect(&mut reader, &name).in_file(&file_path)?;
+        let mut details = Vec::new();
+        let rsz_info = |rsz: &Rsz, details: &mut Vec<(String, String)>| {
+            details.push(("rsz_version".to_string(), format!("{:#x}", rsz.version())));
+            details.push(("roots".to_string(), rsz.roots.len().to_string()));
+            details.push(("types".to_string(), rsz.type_descriptors.len().to_string()));
+            details.push(("extern_slots".to_string(), rsz.extern_slots.len().to_string()));
+        };
+        // these need a key or are too big to load just for the header
+        let resource = match format {
+            FileFormat::Msg | FileFormat::PogList | FileFormat::Oft | FileFormat::Save | FileFormat::Exe => None,
+            _ => Some(Resource::read_format(reader, format, &LoadOptions::default()).in_file(&file_path)?),
+        };
+        match &resource {
+            Some(Resource::User(user)) => {
+                details.push(("resources".to_string(), user.resource_names.len().to_string()));
+                details.push(("children".to_string(), user.children.len().to_string()));
+            }
+            Some(Resource::Pog(pog)) => {
+                details.push(("points".to_string(), pog.points.len().to_string()));
+                details.push(("nodes".to_string(), pog.nodes.len().to_string()));
+            }
+            Some(Resource::Tex(tex)) => {
+                details.push(("width".to_string(), tex.width().to_string()));
+                details.push(("height".to_string(), tex.height().to_string()));
+                details.push(("format".to_string(), format!("{:#04x}", tex.format())));
+                details.push(("mips".to_string(), tex.mip_count().to_string()));
+            }
+            Some(Resource::Mesh(mesh)) => {
+                details.push(("lods".to_string(), mesh.lod_groups.len().to_string()));
+                details.push(("names".to_string(), mesh.names.len().to_string()));
+                details.push(("streaming_ranges".to_string(), mesh.streaming_ranges.len().to_string()));
+            }
+            _ => {}
         }
-        "oft" => {
-            let file = File::open(file_path.clone())?;
-            let oft = Oft::new(file)?;
-            let mut output_path = output_path.clone();
-            output_path.set_file_name(output_path.file_name().unwrap().to_str().unwrap().to_string() + ".otf");
-            std::fs::create_dir_all(output_path.parent().unwrap())?;
-            let mut f = std::fs::File::create(&output_path).expect("Error Creating File");
-            f.write(&oft.data)?;
+        if let Some(resource) = &resource {
+            for rsz in resource.rszs() {
+                rsz_info(rsz, &mut details);
+            }
         }
-        _ => { }
+        Ok(FileInfo {
+            format,
+            path: file_path.clone(),
+            size,
+            details,
+        })
     }
 
-    println!("[INFO] Saved File {:?}", output_path);
-    Ok(())
 }
 
 
@@ -1,5 +1,5 @@
 use crate::align::*;
-use crate::reerr::FileParseError;
+use crate::reerr::{ErrorKind, ReError};
 use nalgebra_glm::*;
 use uuid::Uuid;
 use std::convert::TryInto;
@@ -54,7 +54,7 @@ impl<T: Read + ?Sized> ReadExt for T {
     fn read_bool(&mut self) -> Result<bool> {
         let v = self.read_u8()?;
         if v > 1 {
-            return Err(Box::new(FileParseError::InvalidBool(v)))
+            return Err(Box::new(ReError::new(ErrorKind::InvalidBool(v))))
         }
         Ok(v != 0)
     }
@@ -203,7 +203,7 @@ impl<T: Seek + Read + ?Sized> SeekExt for T {
             let mut buf = vec![0; (from_start - pos).try_into()?];
             self.read_exact(&mut buf)?;
             if buf.into_iter().any(|x| x != 0) {
-                return Err(Box::new(FileParseError::BadAlign(pos, align)))
+                return Err(Box::new(ReError::new(ErrorKind::BadAlign { align }).at(pos)))
             }
         }
 
@@ -215,7 +215,7 @@ impl<T: Seek + Read + ?Sized> SeekExt for T {
         let aligned = align_up(pos, align);
         if aligned != pos {
             let mut buf = vec![0; (aligned - pos).try_into()?];
-            self.read_exact(&mut buf).map_err(|_f| Box::new(FileParseError::BadAlign(pos, align)))?;
+            self.read_exact(&mut buf).map_err(|_f| Box::new(ReError::new(ErrorKind::BadAlign { align }).at(pos)))?;
         }
         Ok(aligned)
     }
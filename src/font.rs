@@ -1,6 +1,6 @@
 use std::io::{Read, Seek};
 use crate::file_ext::*; 
-use crate::reerr::{Result, FileParseError::*};
+use crate::reerr::{Result, ReError};
 
 pub struct Oft {
     pub data: Vec<u8>,
@@ -9,12 +9,8 @@ pub struct Oft {
 impl Oft {
     pub fn new<F: Read + Seek>(mut file: F) -> Result<Oft> {
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?;
-        if ext != "FBFO" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("FBFO"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"FBFO" {
+            return Err(Box::new(ReError::magic(b"FBFO", &magic).at(0)))
         }
         let mut data = vec![];
         file.read_to_end(&mut data)?;
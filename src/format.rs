@@ -0,0 +1,162 @@
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+
+use serde::{Deserialize, Serialize};
+
+use crate::reerr::{ErrorKind, Result, ReError, ResultExt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FileFormat {
+    User,
+    Scn,
+    Pog,
+    PogList,
+    Rsz,
+    Tex,
+    Msg,
+    Mesh,
+    Oft,
+    Save,
+    Exe,
+}
+
+struct MagicEntry {
+    format: FileFormat,
+    offset: usize,
+    magic: &'static [u8],
+}
+
+// Every format we can parse and where its magic is, msg files start with their version
+const MAGICS: &[MagicEntry] = &[
+    MagicEntry { format: FileFormat::User, offset: 0, magic: b"USR\0" },
+    MagicEntry { format: FileFormat::Scn, offset: 0, magic: b"SCN\0" },
+    MagicEntry { format: FileFormat::Pog, offset: 0, magic: b"POG\0" },
+    MagicEntry { format: FileFormat::PogList, offset: 0, magic: b"PGL\0" },
+    MagicEntry { format: FileFormat::Rsz, offset: 0, magic: b"RSZ\0" },
+    MagicEntry { format: FileFormat::Tex, offset: 0, magic: b"TEX\0" },
+    MagicEntry { format: FileFormat::Msg, offset: 4, magic: b"GMSG" },
+    MagicEntry { format: FileFormat::Mesh, offset: 0, magic: b"MESH" },
+    MagicEntry { format: FileFormat::Oft, offset: 0, magic: b"FBFO" },
+    MagicEntry { format: FileFormat::Save, offset: 0, magic: b"DSSS" },
+    MagicEntry { format: FileFormat::Exe, offset: 0, magic: b"MZ" },
+];
+
+const SNIFF_LEN: usize = 8;
+
+impl FileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Scn => "scn",
+            Self::Pog => "pog",
+            Self::PogList => "poglst",
+            Self::Rsz => "rsz",
+            Self::Tex => "tex",
+            Self::Msg => "msg",
+            Self::Mesh => "mesh",
+            Self::Oft => "oft",
+            Self::Save => "bin",
+            Self::Exe => "exe",
+        }
+    }
+
+    pub fn from_extension_str(ext: &str) -> Option<Self> {
+        MAGICS.iter().map(|m| m.format).find(|f| f.extension().eq_ignore_ascii_case(ext))
+    }
+
+    /// Finds the format from the last non numeric extension, so `foo.bar.user.3` is a user file
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.strip_suffix(".json").unwrap_or(file_name);
+        let (_, exts) = file_name.split_once('.')?;
+        exts.rsplit('.')
+            .find(|ext| !ext.is_empty() && !ext.chars().all(|c| c.is_ascii_digit()))
+            .and_then(Self::from_extension_str)
+    }
+
+    /// The numeric suffix after the format extension, so `foo.user.3` gives 3
+    pub fn version_from_file_name(file_name: &str) -> Option<u32> {
+        let file_name = file_name.strip_suffix(".json").unwrap_or(file_name);
+        let (_, exts) = file_name.split_once('.')?;
+        let exts: Vec<&str> = exts.split('.').collect();
+        let ext = exts.iter().rposition(|ext| !ext.is_empty() && !ext.chars().all(|c| c.is_ascii_digit()))?;
+        exts.get(ext + 1)?.parse().ok()
+    }
+
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        MAGICS.iter()
+            .find(|m| header.get(m.offset..m.offset + m.magic.len()) == Some(m.magic))
+            .map(|m| m.format)
+    }
+
+    /// Sniffs the header of the file, the file name is only used if the magic is unknown
+    pub fn detect<R: Read + Seek>(reader: &mut R, file_name: &str) -> Result<Self> {
+        let start = reader.stream_position()?;
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        reader.by_ref().take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(start))?;
+        if let Some(format) = Self::from_magic(&header) {
+            return Ok(format)
+        }
+        if let Some(format) = Self::from_file_name(file_name) {
+            log::debug!("Unknown magic {header:02x?} in {file_name}, using the file name");
+            return Ok(format)
+        }
+        Err(Box::new(ReError::new(ErrorKind::UnknownFormat { magic: header }).at(start)))
+    }
+
+    pub fn detect_file(path: &std::path::Path) -> Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let file_name = path.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+        Self::detect(&mut file, &file_name).in_file(path)
+    }
+
+    /// Formats that carry rsz data
+    pub fn has_rsz(&self) -> bool {
+        matches!(self, Self::User | Self::Scn | Self::Pog | Self::Rsz)
+    }
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn magic_wins_over_file_name() {
+        assert_eq!(FileFormat::detect(&mut Cursor::new(b"USR\0\0\0\0\0"), "renamed.tex.241106027").unwrap(), FileFormat::User);
+        assert_eq!(FileFormat::detect(&mut Cursor::new(b"TEX\0"), "0123abcd").unwrap(), FileFormat::Tex);
+        // msg files start with their version
+        assert_eq!(FileFormat::detect(&mut Cursor::new(b"\x17\0\0\0GMSG"), "").unwrap(), FileFormat::Msg);
+    }
+
+    #[test]
+    fn detect_keeps_position() {
+        let mut reader = Cursor::new(b"junkSCN\0\0\0\0\0".to_vec());
+        reader.set_position(4);
+        assert_eq!(FileFormat::detect(&mut reader, "").unwrap(), FileFormat::Scn);
+        assert_eq!(reader.position(), 4);
+    }
+
+    #[test]
+    fn unknown_magic_uses_file_name() {
+        assert_eq!(FileFormat::detect(&mut Cursor::new(b"\0\0\0\0"), "itemData.user.3").unwrap(), FileFormat::User);
+        assert_eq!(FileFormat::detect(&mut Cursor::new(b""), "a.b.poglst.5").unwrap(), FileFormat::PogList);
+        let e = FileFormat::detect(&mut Cursor::new(b"\0\0\0\0"), "notes.txt").unwrap_err();
+        assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("unknown_format"));
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(FileFormat::from_file_name("foo.bar.user.3.json"), Some(FileFormat::User));
+        assert_eq!(FileFormat::from_file_name("foo.bar"), None);
+        assert_eq!(FileFormat::version_from_file_name("foo.user.3"), Some(3));
+        assert_eq!(FileFormat::version_from_file_name("foo.user.json"), None);
+    }
+}
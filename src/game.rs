@@ -0,0 +1,140 @@
+use std::{cell::Cell, collections::HashMap, sync::OnceLock};
+
+use crate::{
+    config::{DEFAULT_ENUM_FILE, DEFAULT_GAME, DEFAULT_RSZ_FILE},
+    reerr::Result,
+    rsz::dump::{RszMap, RszMapType, RszNameMapType, ENUM_FILE, RSZ_FILE},
+    version::{versions_for, VersionEntry},
+};
+
+pub type EnumMap = HashMap<String, HashMap<String, String>>;
+
+/// Everything that depends on which game the files come from: the rsz dump, the enums and the file versions with
+/// their layouts. The version table is picked by the name of the game. The dumps are loaded the first time they are needed
+pub struct GameProfile {
+    pub name: String,
+    pub rsz_file: String,
+    pub enum_file: String,
+    pub versions: &'static [VersionEntry],
+    rsz: OnceLock<RszMap<RszMapType>>,
+    names: OnceLock<RszMap<RszNameMapType>>,
+    enums: OnceLock<EnumMap>,
+}
+
+static DEFAULT: OnceLock<GameProfile> = OnceLock::new();
+
+thread_local! {
+    static ACTIVE: Cell<Option<&'static GameProfile>> = const { Cell::new(None) };
+}
+
+impl GameProfile {
+    pub fn new(name: &str, rsz_file: &str, enum_file: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            rsz_file: rsz_file.to_string(),
+            enum_file: enum_file.to_string(),
+            versions: versions_for(name)?,
+            rsz: OnceLock::new(),
+            names: OnceLock::new(),
+            enums: OnceLock::new(),
+        })
+    }
+
+    /// Profiles live for the whole process so the dumps can be borrowed from anywhere
+    pub fn leak(self) -> &'static Self {
+        Box::leak(Box::new(self))
+    }
+
+    /// Makes this the profile of threads that did not enter one, it can only be set before it is first used
+    pub fn set_default(self) -> Result<()> {
+        let name = self.name.clone();
+        DEFAULT.set(self).map_err(|_| format!("Could not use game profile {name}, the default profile is already in use").into())
+    }
+
+    /// Profile used by threads that did not enter one, the default game with `RSZ_FILE` and `ENUM_FILE` if none was set
+    pub fn default_profile() -> &'static Self {
+        DEFAULT.get_or_init(|| {
+            let rsz_file = RSZ_FILE.get_or_init(|| DEFAULT_RSZ_FILE.to_string());
+            let enum_file = ENUM_FILE.get_or_init(|| DEFAULT_ENUM_FILE.to_string());
+            Self::new(DEFAULT_GAME, rsz_file, enum_file).expect("the default game has a version table")
+        })
+    }
+
+    /// The profile of the current thread
+    pub fn active() -> &'static Self {
+        ACTIVE.get().unwrap_or_else(Self::default_profile)
+    }
+
+    /// Runs `f` with this profile active on the current thread
+    pub fn enter<T>(&'static self, f: impl FnOnce() -> T) -> T {
+        struct Restore(Option<&'static GameProfile>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                ACTIVE.set(self.0);
+            }
+        }
+        let _restore = Restore(ACTIVE.replace(Some(self)));
+        f()
+    }
+
+    pub fn rsz_map(&self) -> &RszMap<RszMapType> {
+        self.rsz.get_or_init(|| {
+            let file = std::fs::read_to_string(&self.rsz_file).unwrap();
+            RszMap(serde_json::from_str(&file).unwrap())
+        })
+    }
+
+    pub fn name_map(&self) -> &RszMap<RszNameMapType> {
+        self.names.get_or_init(|| {
+            let names = self.rsz_map().0.iter()
+                .map(|(hash, rsz_struct)| (rsz_struct.name.clone(), u32::from_str_radix(hash, 16).unwrap()))
+                .collect();
+            RszMap(names)
+        })
+    }
+
+    pub fn enum_map(&self) -> &EnumMap {
+        self.enums.get_or_init(|| {
+            let file = std::fs::read_to_string(&self.enum_file).unwrap();
+            serde_json::from_str(&file).unwrap()
+        })
+    }
+}
+
+#[cfg(test)]
+impl GameProfile {
+    /// A profile with the given rsz dump and no enums, so tests don't need the dump files
+    pub(crate) fn with_rsz_dump(rsz: serde_json::Value) -> &'static Self {
+        let profile = Self::new(DEFAULT_GAME, "", "").unwrap();
+        let _ = profile.rsz.set(RszMap(serde_json::from_value(rsz).unwrap()));
+        let _ = profile.enums.set(EnumMap::new());
+        profile.leak()
+    }
+}
+
+/// Field of a type in a test rsz dump
+#[cfg(test)]
+pub(crate) fn dump_field(name: &str, r#type: &str, original_type: &str, array: bool) -> serde_json::Value {
+    serde_json::json!({ "name": name, "type": r#type, "original_type": original_type, "array": array, "align": 4, "size": 4, "native": false })
+}
+
+/// Rsz dump for tests with app.Item, a name and an attack, and app.Root, which holds an item in `_Extra` and a list
+/// of them in `_Values`
+#[cfg(test)]
+pub(crate) fn item_dump() -> serde_json::Value {
+    serde_json::json!({
+        "0": { "name": "", "crc": "0", "fields": [] },
+        "100": { "name": "app.Item", "crc": "1", "fields": [dump_field("_Name", "String", "System.String", false), dump_field("_Attack", "S32", "System.Int32", false)] },
+        "200": { "name": "app.Root", "crc": "2", "fields": [dump_field("_Extra", "Object", "app.Item", false), dump_field("_Values", "Object", "app.Item", true)] },
+    })
+}
+
+impl std::fmt::Debug for GameProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameProfile")
+            .field("name", &self.name)
+            .field("rsz_file", &self.rsz_file)
+            .field("enum_file", &self.enum_file)
+            .finish_non_exhaustive()
+    }
+}
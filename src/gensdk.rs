@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
@@ -113,7 +113,7 @@ impl Sdk {
             files: HashMap::new(),
         }
     }
-    pub fn add_types(&mut self, types: HashSet<u32>) -> Result<()> {
+    pub fn add_types(&mut self, types: BTreeSet<u32>) -> Result<()> {
         let mut structs = Vec::new();
         let mut queue: VecDeque<u32> = types.into_iter().collect();
         if let Some(charthings) = RszDump::name_map().get(&"app.user_data.CharacterEditThumbnailTextureData".to_string()) {
@@ -1,10 +1,10 @@
 pub mod editor;
 use core::f32;
-use std::{fs::File, path::PathBuf, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex}};
+use std::{path::PathBuf, sync::mpsc::{self, Receiver, Sender}};
 
 use eframe::egui::{self, Color32, FontDefinitions, FontFamily, FontSelection, Frame, ScrollArea, TextEdit, TextStyle};
 use egui_json_tree::{render::{DefaultRender, RenderContext}, *};
-use mhtame::{edit::{Edit, EditableFile, RszEditCtx}, file::{FileReader, StructRW}, rsz::{dump::{RszDump, ENUM_FILE, RSZ_FILE}, rszserde::DeRsz}, save::{types::to_dersz, SaveContext, SaveFile}, user::User};
+use mhtame::{config::{Config, Profile}, edit::{Edit, EditableFile, RszEditCtx}, file::FileReader, resource::{LoadOptions, Resource}, user::User, vfs::LayeredVfs};
 use rug::az::UnwrappedAs;
 use serde_json::json;
 use clap::{Parser};
@@ -13,18 +13,34 @@ use crate::editor::Editor;
 #[derive(Parser, Debug)]
 #[command(name = "mhtame-gui")]
 #[command(version, about, long_about = None)]
-struct GuiArgs {
+pub struct GuiArgs {
     #[arg(short('f'), long)]
     file_name: Option<String>,
 
-    #[arg(short('o'), long, default_value_t = String::from("outputs"))]
-    out_dir: String,
+    #[arg(short('o'), long)]
+    out_dir: Option<String>,
 
-    #[arg(long, default_value_t = String::from("enums.json"))]
-    enums: String,
+    #[arg(long)]
+    rsz: Option<String>,
+
+    #[arg(long)]
+    enums: Option<String>,
 
     #[arg(long)]
     steamid: Option<String>,
+
+    /// Config file with profiles, defaults to mhtame.toml in the working directory
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(short('P'), long)]
+    profile: Option<String>,
+}
+
+fn load_profile(args: &GuiArgs) -> mhtame::reerr::Result<Profile> {
+    let profile = Config::find(args.config.as_deref())?.profile(args.profile.as_deref())?;
+    profile.set_schema_files(args.rsz.as_deref(), args.enums.as_deref())?;
+    Ok(profile)
 }
 
 pub fn main() -> eframe::Result<()> {
@@ -34,12 +50,17 @@ pub fn main() -> eframe::Result<()> {
         viewport: egui::ViewportBuilder::default().with_drag_and_drop(true),
         .. Default::default()
     };
-    ENUM_FILE.set("enums.json".to_string()).unwrap();
-    RSZ_FILE.set("rszmhwilds_unpacked_structs.json".to_string()).unwrap();
+    let profile = match load_profile(&args) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("[ERROR] {e}");
+            std::process::exit(2);
+        }
+    };
     eframe::run_native("mhtame",
         options,
         Box::new(|cc| {
-            Ok(Box::new(TameApp::new(args)))
+            Ok(Box::new(TameApp::new(args, &profile)))
         }),
     )
 }
@@ -51,7 +72,6 @@ pub struct TameApp {
     file_reader: FileReader,
     json_value: Option<serde_json::Value>,
     user_value: Option<User>,
-    dersz: Option<DeRsz>,
     tx: Sender<PathBuf>,
     rx: Receiver<PathBuf>,
     updated: bool,
@@ -59,10 +79,15 @@ pub struct TameApp {
 }
 
 impl TameApp {
-    pub fn new(args: GuiArgs) -> Self {
-        let file_reader = FileReader::new(args.out_dir.into(), None, false, false, true, args.steamid.clone());
+    pub fn new(args: GuiArgs, profile: &Profile) -> Self {
+        let steamid = args.steamid.or(profile.steamid.clone());
+        let mut file_reader = FileReader::new(profile.out_dir(args.out_dir).into(), profile.root_dir.clone(), false, false, true, steamid.clone());
+        match profile.pak_key().and_then(|key| LayeredVfs::open(&profile.roots(), key.as_ref(), None)) {
+            Ok(vfs) => file_reader.set_vfs(Box::new(vfs)),
+            Err(e) => eprintln!("[ERROR] Could not open the profile roots: {e}"),
+        }
         let (tx, rx) = mpsc::channel();
-        let steamid = if let Some(x) = args.steamid {
+        let steamid = if let Some(x) = steamid {
             let u = if let Some(hex) = x.clone().strip_prefix("0x") {
                 u64::from_str_radix(hex, 16).ok()
             } else {
@@ -77,7 +102,6 @@ impl TameApp {
             steam_id: steamid,
             json_value: None,
             user_value: None,
-            dersz: None,
             tx, rx,
             updated: false,
             current_file: None,
@@ -86,6 +110,13 @@ impl TameApp {
     }
 }
 
+impl TameApp {
+    fn load(&self, path: PathBuf) -> mhtame::reerr::Result<Box<EditableFile>> {
+        let options = LoadOptions { steamid: self.steam_id, ..Default::default() };
+        Resource::load(path, &options)?.into_editable()
+    }
+}
+
 impl Default for TameApp {
     fn default() -> Self {
         let file_reader = FileReader::new("outputs".into(), None, false, false, true, None);
@@ -97,7 +128,6 @@ impl Default for TameApp {
             steam_id: None,
             json_value: None,
             user_value: None,
-            dersz: None,
             tx, rx,
             updated: false,
             current_file: None,
@@ -144,29 +174,27 @@ impl eframe::App for TameApp {
 
                 if !self.updated {
                     if let Some(path) = &self.current_file_name {
-                        let reader = File::open(path).unwrap();
-                        if let Ok(user) = User::new(&reader) {
-                            let result = user.rsz.deserialize_to_dersz().unwrap();
-                            self.current_file = Some(Box::new(result));
-                        }
-                        let mut reader = File::open(path).unwrap();
-                        if let Ok(save) = SaveFile::read(&mut reader, &mut SaveContext { key: self.steam_id.unwrap_or(0) }) {
-                            self.current_file = Some(Box::new(save));
+                        match self.load(PathBuf::from(path)) {
+                            Ok(file) => {
+                                self.current_file = Some(file);
+                                println!("Loaded");
+                            }
+                            Err(e) => eprintln!("[ERROR] Could not load {path}: {e}"),
                         }
                         //self.dersz = Some(result);
                         self.updated = true;
-                        println!("Loaded");
                     }
                 }
 
                 if let Ok(result) = self.rx.try_recv() {
                     self.current_file_name = Some(result.display().to_string());
-                    let reader = File::open(result).unwrap();
-                    let user = User::new(reader).unwrap();
-                    let result = user.rsz.deserialize_to_dersz().unwrap();
-                    self.dersz = Some(result);
-                    println!("{}", serde_json::json!(self.dersz));
-                    println!("Loaded");
+                    match self.load(result.clone()) {
+                        Ok(file) => {
+                            self.current_file = Some(file);
+                            println!("Loaded");
+                        }
+                        Err(e) => eprintln!("[ERROR] Could not load {}: {e}", result.display()),
+                    }
                 }
 
                 /*if ui.button("Select File").clicked() {
@@ -0,0 +1,123 @@
+use std::collections::{BTreeSet, HashMap};
+
+use fasthash::{murmur3, FastHash};
+use serde::Serialize;
+
+use crate::{pak::{Pak, PathList}, reerr::Result, rsz::dump::RszDump, save::SaveFile};
+
+/// Seed the engine uses for every murmur3 name hash
+pub const SEED: u32 = 0xffffffff;
+
+pub fn murmur3(bytes: &[u8]) -> u32 {
+    murmur3::Hash32::hash_with_seed(bytes, SEED)
+}
+
+/// Field names in rsz types and save files are hashed as utf8
+pub fn utf8_hash(name: &str) -> u32 {
+    murmur3(name.as_bytes())
+}
+
+/// Paths and resource names are hashed as utf16
+pub fn utf16_hash(name: &str) -> u32 {
+    let bytes: Vec<u8> = name.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    murmur3(&bytes)
+}
+
+/// Hash of a pak entry path, the lowercase hash in the low half and the uppercase one in the high half
+pub fn path_hash(path: &str) -> u64 {
+    let path = path.replace('\\', "/");
+    (utf16_hash(&path.to_uppercase()) as u64) << 32 | utf16_hash(&path.to_lowercase()) as u64
+}
+
+/// Every hash a name can show up as
+#[derive(Debug, Clone, Serialize)]
+pub struct NameHashes {
+    pub name: String,
+    pub utf8: u32,
+    pub utf16: u32,
+    pub lower: u32,
+    pub upper: u32,
+    pub path: u64,
+}
+
+impl NameHashes {
+    pub fn new(name: &str) -> Self {
+        let path = path_hash(name);
+        Self {
+            name: name.to_string(),
+            utf8: utf8_hash(name),
+            utf16: utf16_hash(name),
+            lower: path as u32,
+            upper: (path >> 32) as u32,
+            path,
+        }
+    }
+}
+
+impl std::fmt::Display for NameHashes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08x} {:08x} {:016x} {}", self.utf8, self.utf16, self.path, self.name)
+    }
+}
+
+/// Names found behind hashes and the hashes no name matched, as hex
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Discovery {
+    pub total: usize,
+    pub found: BTreeSet<String>,
+    pub unresolved: Vec<String>,
+}
+
+impl Discovery {
+    /// Names of the pak entries in the list and the hashes of the entries that are not
+    pub fn pak<R: std::io::Read + std::io::Seek>(pak: &Pak<R>, names: &PathList) -> Self {
+        Self {
+            total: pak.entries.len(),
+            found: pak.resolve(names).into_iter().map(str::to_string).collect(),
+            unresolved: pak.unresolved(names).map(|entry| format!("{:016x}", entry.hash)).collect(),
+        }
+    }
+
+    /// Field names of a save file, the fields of the types in the rsz dump are tried along with the list
+    pub fn save(save: &SaveFile, names: &PathList) -> Result<Self> {
+        let mut types = BTreeSet::new();
+        let mut fields = BTreeSet::new();
+        save.data.hashes(&mut types, &mut fields);
+        save.detail.hashes(&mut types, &mut fields);
+
+        let mut known: HashMap<u32, &str> = names.paths.iter().map(|name| (utf8_hash(name), name.as_str())).collect();
+        for hash in &types {
+            if let Ok(info) = RszDump::get_struct(*hash) {
+                known.extend(info.fields.iter().map(|field| (utf8_hash(&field.name), field.name.as_str())));
+            }
+        }
+        let mut discovery = Self { total: fields.len(), ..Default::default() };
+        for hash in fields {
+            match known.get(&hash) {
+                Some(name) => { discovery.found.insert(name.to_string()); }
+                None => discovery.unresolved.push(format!("{hash:08x}")),
+            }
+        }
+        Ok(discovery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn murmur3_seed() {
+        // reference value of murmur3_32 for no input with this seed
+        assert_eq!(murmur3(b""), 0x81f16f39);
+    }
+
+    #[test]
+    fn game_path() {
+        let path = "natives/STM/GameDesign/Common/Item/ItemData.user.3";
+        assert_eq!(path_hash(path), 0x4bf29df1_0bad4732);
+        assert_eq!(path_hash("NATIVES\\stm\\GameDesign\\Common\\Item\\itemdata.USER.3"), path_hash(path));
+        let hashes = NameHashes::new(path);
+        assert_eq!((hashes.lower, hashes.upper), (0x0bad4732, 0x4bf29df1));
+    }
+}
@@ -3,11 +3,24 @@ use std::io::Result;
 pub mod edit;
 pub mod align;
 pub mod gensdk;
+pub mod game;
+pub mod hash;
 pub mod reerr;
 pub mod bitfield;
+pub mod config;
 pub mod compression;
 pub mod file_ext;
 pub mod file;
+pub mod format;
+pub mod manifest;
+pub mod pak;
+pub mod report;
+pub mod resource;
+pub mod walk;
+pub mod version;
+pub mod vfs;
+pub mod verify;
+pub mod search;
 pub mod save;
 pub mod msg;
 pub mod rsz;
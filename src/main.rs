@@ -1,7 +1,10 @@
 pub mod gensdk;
+pub mod game;
+pub mod hash;
 pub mod align;
 pub mod reerr;
 pub mod bitfield;
+pub mod config;
 pub mod compression;
 pub mod file_ext;
 pub mod msg;
@@ -13,6 +16,16 @@ pub mod font;
 pub mod scn;
 pub mod mesh;
 pub mod file;
+pub mod format;
+pub mod manifest;
+pub mod pak;
+pub mod report;
+pub mod resource;
+pub mod walk;
+pub mod version;
+pub mod vfs;
+pub mod verify;
+pub mod search;
 pub mod save;
 pub mod tdb;
 pub mod edit;
@@ -21,117 +34,637 @@ extern crate image;
 extern crate libdeflater;
 
 
-use clap::Parser;
-use file::FileReader;
-use rsz::dump::{ENUM_FILE, RSZ_FILE};
+use clap::{Args, Parser, Subcommand};
+use config::{Config, Profile};
+use file::{FileReader, StructRW};
+use format::FileFormat;
+use hash::{Discovery, NameHashes};
+use pak::{Compression, Pak, PathList};
+use report::ReportFormat;
+use resource::{build_from_json, LoadOptions, OutputStyle, Resource};
+use rsz::dump::RszDump;
+use save::{parse_steamid, SaveContext, SaveFile, SaveHeader};
+use search::{Predicate, Query};
+use vfs::{vfs_path, DirVfs, LayeredVfs, Vfs};
+use walk::WalkFilter;
 
 use std::error::Error;
-use std::fs::{read_to_string};
-use std::path::{Path, PathBuf};
+use std::fs::{read_to_string, File};
+use std::io::{BufReader, Cursor, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::SystemTime;
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// Exit code when some of the input files could not be processed
+const EXIT_FILES_FAILED: u8 = 1;
+/// Exit code when the command could not run at all
+const EXIT_FATAL: u8 = 2;
+
 #[derive(Parser, Debug)]
 #[command(name = "mhtame")]
 #[command(version, about, long_about = None)]
-struct Args {
-    #[arg(short('f'), long)]
-    file_name: Option<String>,
-    
+#[command(after_help = "Exit codes: 0 on success, 1 if some files failed, 2 if the command could not run")]
+struct Cli {
+    /// Rsz dump, defaults to the profile's or rszmhwilds.json
+    #[arg(long, global = true)]
+    rsz: Option<String>,
+
+    /// Enum dump, defaults to the profile's or enums.json
+    #[arg(long, global = true)]
+    enums: Option<String>,
+
+    /// Config file with profiles, defaults to mhtame.toml in the working directory
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Profile from the config to use instead of its default one
+    #[arg(short('P'), long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Debug)]
+struct InputArgs {
+    /// Files or directories to process, relative to the root directory. Directories are walked recursively
+    files: Vec<PathBuf>,
+
+    /// File containing a list of files to process, one per line
+    #[arg(short('l'), long)]
+    list: Option<PathBuf>,
+
+    /// Root directory or pak, usually the natives folder of the game.
+    /// Can be repeated, files in later roots override the ones in earlier roots
     #[arg(short('r'), long)]
-    root_dir: Option<String>,
+    root_dir: Vec<PathBuf>,
+
+    /// Only take files whose path matches this glob, can be repeated
+    #[arg(short('i'), long)]
+    include: Vec<String>,
+
+    /// Skip files and directories that match this glob, can be repeated
+    #[arg(short('x'), long)]
+    exclude: Vec<String>,
+
+    /// Only take files with this extension, can be repeated
+    #[arg(short('e'), long("ext"))]
+    extensions: Vec<String>,
+
+    /// Path list used to find files when the root is a pak, one path per line
+    #[arg(long)]
+    pak_paths: Option<PathBuf>,
+}
+
+impl InputArgs {
+    /// `-` as the only input reads it from stdin and writes the result to stdout
+    fn is_stdin(&self) -> bool {
+        self.list.is_none() && self.files.len() == 1 && self.files[0].as_os_str() == "-"
+    }
 
+    /// Roots from the profile are only used when none are given
+    fn with_profile(mut self, profile: &Profile) -> Self {
+        if self.root_dir.is_empty() {
+            self.root_dir = profile.roots();
+        }
+        self.pak_paths = self.pak_paths.or(profile.pak_paths.clone());
+        self
+    }
+
+    /// The first root, output paths keep the path inside it
+    fn root(&self) -> Option<PathBuf> {
+        self.root_dir.first().cloned()
+    }
+
+    /// Stacks the roots, the ones that are files are opened as paks
+    fn open_vfs(&self, profile: &Profile) -> Result<LayeredVfs> {
+        let paths = match &self.pak_paths {
+            Some(paths) if self.root_dir.iter().any(|root| root.is_file()) => Some(PathList::load(paths)?),
+            _ => None,
+        };
+        LayeredVfs::open(&self.root_dir, profile.pak_key()?.as_ref(), paths.as_ref())
+    }
+
+    /// Lists the input files and gives the reader the roots to read them from
+    fn load(&self, profile: &Profile, mut file_reader: FileReader) -> Result<(Vec<PathBuf>, FileReader)> {
+        let vfs = self.open_vfs(profile)?;
+        let list = self.files(&vfs)?;
+        file_reader.set_vfs(Box::new(vfs));
+        Ok((list, file_reader))
+    }
+
+    fn files(&self, vfs: &dyn Vfs) -> Result<Vec<PathBuf>> {
+        let mut list: Vec<PathBuf> = Vec::new();
+        if let Some(lf) = &self.list {
+            let lf = read_to_string(lf).map_err(|e| format!("Could not open list file {lf:?}: {e}"))?;
+            for line in lf.lines() {
+                list.push(PathBuf::from(line));
+            }
+        }
+        list.extend(self.files.iter().cloned());
+        let filter = WalkFilter::new(&self.include, &self.exclude, &self.extensions);
+        let mut files = Vec::new();
+        for entry in list {
+            let found = vfs.list(&vfs_path(&entry), &filter)?;
+            if found.is_empty() {
+                return Err(format!("{entry:?} was not found in {}", vfs.describe()).into())
+            }
+            files.extend(found.into_iter().map(PathBuf::from));
+        }
+        let list = files;
+        if list.is_empty() {
+            return Err("No input files given".into())
+        }
+        Ok(list)
+    }
+}
+
+#[derive(Args, Debug)]
+struct HashArgs {
+    /// Names to hash
+    names: Vec<String>,
+
+    /// File containing names to hash, one per line
     #[arg(short('l'), long)]
-    list: Option<String>, 
-
-    #[arg(short('p'), long, default_value_t = true)]
-    preserve: bool,
-
-    #[arg(short('d'), long("dump-rsz"), default_value_t = false)]
-    try_dump_rsz: bool,
-    
-    #[arg(short('s'), long("dump-sdk"), default_value_t = false)]
-    dump_sdk: bool,
-    
-    #[arg(short('o'), long, default_value_t = String::from("outputs"))]
-    out_dir: String,
-    
-    #[arg(long, default_value_t = String::from("rszmhwilds.json"))]
-    rsz: String,
-    
-    #[arg(long, default_value_t = String::from("enums.json"))]
-    enums: String,
+    list: Option<PathBuf>,
+
+    /// Report the entries of this pak that no name resolves
+    #[arg(long, conflicts_with = "save")]
+    pak: Option<PathBuf>,
 
+    /// Report the field name hashes of this save file that no name resolves
     #[arg(long)]
-    save_file: Option<PathBuf>,
+    save: Option<PathBuf>,
 
+    /// Steam id used to decrypt the save file
     #[arg(long)]
     steamid: Option<String>,
+
+    /// Add the names that resolved a hash to this name list
+    #[arg(short('o'), long)]
+    output: Option<PathBuf>,
+
+    /// Print the hashes or the result as json
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Dump files into json, png or otf
+    Dump {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[arg(short('o'), long)]
+        out_dir: Option<String>,
+
+        /// Strip the root directory but keep the rest of the path in the output directory
+        #[arg(short('p'), long, num_args = 0..=1, default_missing_value = "true")]
+        preserve: Option<bool>,
+
+        /// Steam id used to decrypt save files
+        #[arg(long)]
+        steamid: Option<String>,
+
+        /// Write a report with the result of every file, - writes it to stdout
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        report_format: ReportFormat,
+
+        /// Dump every file even if the manifest says it did not change
+        #[arg(long)]
+        force: bool,
+
+        /// Format of the input read from stdin, detected from the magic if not given
+        #[arg(long, value_parser = parse_format)]
+        format: Option<FileFormat>,
+
+        /// How json is written
+        #[arg(long, value_enum, default_value_t = OutputStyle::Pretty)]
+        style: OutputStyle,
+
+        /// Keep objects of types missing from the rsz dump as raw bytes instead of failing the file
+        #[arg(long)]
+        lenient: bool,
+    },
+    /// Recreate binary files from json dumps, only user files are supported
+    Build {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[arg(short('o'), long)]
+        out_dir: Option<String>,
+
+        /// Format of the json read from stdin
+        #[arg(long, value_parser = parse_format)]
+        format: Option<FileFormat>,
+
+        /// Also pack the rebuilt files into this pak, with their path inside the output directory
+        #[arg(long)]
+        pak: Option<PathBuf>,
+
+        /// Compression of the pak entries
+        #[arg(long, value_enum, default_value_t = Compression::None)]
+        compress: Compression,
+
+        /// Write over files in the input directory, like the game file the json was dumped from
+        #[arg(long)]
+        force: bool,
+    },
+    /// Pack files into a pak, their path inside it is the one relative to the root directory
+    Pack {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[arg(short('o'), long)]
+        output: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = Compression::None)]
+        compress: Compression,
+    },
+    /// Dump user files and build them back, then report where the rebuilt files differ from the originals
+    Verify {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Go through lossless json instead of the compact json
+        #[arg(long, default_value_t = false)]
+        lossless: bool,
+    },
+    /// Find the objects of a type, or with fields matching some conditions, in user, scn and pog files
+    Search {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Only objects of this type, like app.user_data.WeaponData.cData
+        #[arg(short('t'), long("type"))]
+        type_name: Option<String>,
+
+        /// Condition on a field, like "_Attack>200". Can be repeated, objects have to match all of them
+        #[arg(short('w'), long("where"))]
+        predicates: Vec<Predicate>,
+
+        /// Print every match as a line of json
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Keep objects of types missing from the rsz dump as raw bytes instead of failing the file, they never match
+        #[arg(long)]
+        lenient: bool,
+    },
+    /// Hash names like the engine does, or find which hashes of a pak or save file are still unknown
+    Hash(HashArgs),
+    /// Print header information about files
+    Info {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// Print the information as json
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Decrypt or encrypt save files
+    #[command(subcommand)]
+    Save(SaveCommand),
+    /// Generate the rust sdk for the types used in rsz files
+    Sdk {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// List the rsz types used in files
+    Types {
+        #[command(flatten)]
+        input: InputArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SaveCommand {
+    /// Decrypt a save file and dump it to json
+    Decrypt {
+        file: PathBuf,
+
+        #[arg(long)]
+        steamid: Option<String>,
+
+        #[arg(short('o'), long)]
+        out_dir: Option<String>,
+
+        /// Write the decrypted payload with its header instead of json, this can be encrypted again
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+    /// Encrypt a raw payload written by `save decrypt --raw`
+    Encrypt {
+        file: PathBuf,
+
+        #[arg(long)]
+        steamid: Option<String>,
+
+        #[arg(short('o'), long)]
+        output: PathBuf,
+    },
+}
+
+impl Cli {
+    fn is_stdin(&self) -> bool {
+        match &self.command {
+            Command::Dump { input, .. } | Command::Build { input, .. } => input.is_stdin(),
+            _ => false,
+        }
+    }
+}
+
+fn parse_format(ext: &str) -> std::result::Result<FileFormat, String> {
+    FileFormat::from_extension_str(ext).ok_or(format!("Unknown format {ext}"))
+}
+
+/// Reads a resource from stdin and writes its export to stdout
+fn dump_stdin(format: Option<FileFormat>, steamid: Option<String>, style: OutputStyle, lenient: bool) -> Result<()> {
+    let mut data = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut data)?;
+    if data.trim_ascii_start().starts_with(b"{") {
+        return Err("stdin is json, use the build command to recreate it".into())
+    }
+    let options = LoadOptions { steamid: steamid.as_deref().map(parse_steamid).transpose()?, lenient };
+    let resource = match format {
+        Some(format) => Resource::read_format(Cursor::new(data), format, &options)?,
+        None => Resource::from_reader(Cursor::new(data), "-", &options)?,
+    };
+    let mut stdout = std::io::stdout().lock();
+    resource.write_export(&mut stdout, style)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Reads a json dump from stdin and writes the rebuilt binary to stdout
+fn build_stdin(format: Option<FileFormat>) -> Result<()> {
+    let format = format.ok_or("Need --format to build from stdin")?;
+    let json = std::io::read_to_string(std::io::stdin().lock())?;
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&build_from_json(&json, format)?)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn profile_steamid(steamid: Option<String>, profile: &Profile) -> Result<String> {
+    steamid.or(profile.steamid.clone())
+        .ok_or("Need a steamid, give it with --steamid or in the profile".into())
 }
 
-#[allow(dead_code)]
-fn find_files_with_extension(base_dir: PathBuf, extension: &str) -> Vec<PathBuf> {
-    let mut results = Vec::new();
-    let mut paths: Vec<PathBuf> = Vec::new();
-    paths.push(base_dir);
-    while let Some(dir) = paths.pop() {
-        if let Ok(entries) = std::fs::read_dir(dir) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_dir() {
-                    paths.push(path);
-                } else {
-                    if let Some(x) = path.file_name().unwrap().to_str() {
-                        if x.ends_with(extension) {
-                            results.push(path);
+fn files_exit_code(failed: usize) -> ExitCode {
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("[ERROR] {failed} file(s) failed");
+        ExitCode::from(EXIT_FILES_FAILED)
+    }
+}
+
+fn run_hash(args: HashArgs, profile: &Profile) -> Result<ExitCode> {
+    let mut names = match &args.list {
+        Some(list) => PathList::load(list)?,
+        None => PathList::default(),
+    };
+    names.paths.extend(args.names);
+    let discovery = if let Some(pak) = &args.pak {
+        Discovery::pak(&Pak::open(pak, profile.pak_key()?.as_ref())?, &names)
+    } else if let Some(save) = &args.save {
+        let key = parse_steamid(&profile_steamid(args.steamid, profile)?)?;
+        let save = SaveFile::read(&mut BufReader::new(File::open(save)?), &mut SaveContext { key })?;
+        Discovery::save(&save, &names)?
+    } else {
+        for name in &names.paths {
+            let hashes = NameHashes::new(name);
+            if args.json {
+                println!("{}", serde_json::to_string(&hashes)?);
+            } else {
+                println!("{hashes}");
+            }
+        }
+        return Ok(ExitCode::SUCCESS)
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&discovery)?);
+    } else {
+        for hash in &discovery.unresolved {
+            println!("{hash}");
+        }
+        println!("[INFO] Resolved {} of {} hashes", discovery.total - discovery.unresolved.len(), discovery.total);
+    }
+    if let Some(output) = &args.output {
+        let mut list = if output.exists() { PathList::load(output)? } else { PathList::default() };
+        list.paths.extend(discovery.found);
+        list.paths.sort();
+        list.paths.dedup();
+        list.save(output)?;
+        println!("[INFO] Saved Name List {:?} with {} names", output, list.paths.len());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_save(command: SaveCommand, profile: &Profile) -> Result<ExitCode> {
+    match command {
+        SaveCommand::Decrypt { file, steamid, out_dir, raw } => {
+            let steamid = profile_steamid(steamid, profile)?;
+            let key = parse_steamid(&steamid)?;
+            let file_name = file.file_name().ok_or("Path does not contain file")?.to_string_lossy().to_string();
+            let output_path = PathBuf::from(profile.out_dir(out_dir));
+            std::fs::create_dir_all(&output_path)?;
+            if raw {
+                let mut reader = File::open(&file)?;
+                let (header, decrypted) = SaveFile::decrypt(&mut reader, key)?;
+                let output_path = output_path.join(file_name + ".decrypted");
+                let mut f = File::create(&output_path)?;
+                header.write(&mut f)?;
+                f.write_all(&decrypted)?;
+                println!("[INFO] Saved File {:?}", output_path);
+            } else {
+                let file_reader = FileReader::new(output_path, None, false, false, false, Some(steamid));
+                file_reader.dump_file(&file)?;
+            }
+        }
+        SaveCommand::Encrypt { file, steamid, output } => {
+            let key = parse_steamid(&profile_steamid(steamid, profile)?)?;
+            let mut buf = vec![];
+            File::open(&file)?.read_to_end(&mut buf)?;
+            let mut reader = Cursor::new(&buf);
+            let header = SaveHeader::read(&mut reader)?;
+            let encrypted = SaveFile::encrypt(&header, &buf[SaveHeader::SIZE..], key)?;
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            File::create(&output)?.write_all(&encrypted)?;
+            println!("[INFO] Saved File {:?}", output);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    let config = Config::find(cli.config.as_deref())?;
+    let profile = config.profile(cli.profile.as_deref())?;
+    profile.set_schema_files(cli.rsz.as_deref(), cli.enums.as_deref())?;
+    let code = match cli.command {
+        Command::Dump { input, steamid, format, style, lenient, .. } if input.is_stdin() => {
+            dump_stdin(format, steamid.or(profile.steamid.clone()), style, lenient)?;
+            ExitCode::SUCCESS
+        }
+        Command::Dump { input, out_dir, preserve, steamid, report, report_format, force, style, lenient, .. } => {
+            let input = input.with_profile(&profile);
+            let preserve = preserve.or(profile.preserve).unwrap_or(true);
+            let steamid = steamid.or(profile.steamid.clone());
+            let (list, mut file_reader) = input.load(&profile, FileReader::new(profile.out_dir(out_dir).into(), input.root(), false, false, preserve, steamid))?;
+            file_reader.set_incremental(!force);
+            file_reader.set_output_style(style);
+            file_reader.set_lenient(lenient);
+            file_reader.set_log_stderr(report.as_ref().is_some_and(|path| path.as_os_str() == "-"));
+            let run_report = file_reader.dump_files(list)?;
+            match report {
+                Some(path) if path.as_os_str() == "-" => run_report.write(&mut std::io::stdout().lock(), report_format)?,
+                Some(path) => {
+                    run_report.save(&path, report_format)?;
+                    println!("[INFO] Saved Report {:?}", path);
+                }
+                None => {}
+            }
+            files_exit_code(run_report.failed())
+        }
+        Command::Build { input, format, .. } if input.is_stdin() => {
+            build_stdin(format)?;
+            ExitCode::SUCCESS
+        }
+        Command::Build { input, out_dir, pak, compress, force, .. } => {
+            let input = input.with_profile(&profile);
+            let out_dir = PathBuf::from(profile.out_dir(out_dir));
+            let (list, mut file_reader) = input.load(&profile, FileReader::new(out_dir.clone(), input.root(), false, false, true, None))?;
+            file_reader.set_overwrite(force);
+            let (outputs, failed) = file_reader.build_files(list);
+            if let Some(pak) = pak {
+                let outputs: Vec<String> = outputs.iter().map(|output| vfs_path(output.strip_prefix(&out_dir).unwrap_or(output))).collect();
+                FileReader::pack_files(&DirVfs::new(out_dir), &outputs, &pak, compress)?;
+            }
+            files_exit_code(failed)
+        }
+        Command::Pack { input, output, compress } => {
+            let input = input.with_profile(&profile);
+            let vfs = input.open_vfs(&profile)?;
+            let files: Vec<String> = input.files(&vfs)?.iter().map(|file| vfs_path(file)).collect();
+            FileReader::pack_files(&vfs, &files, &output, compress)?;
+            ExitCode::SUCCESS
+        }
+        Command::Verify { input, lossless } => {
+            let input = input.with_profile(&profile);
+            let (list, file_reader) = input.load(&profile, FileReader::new("".into(), input.root(), false, false, true, None))?;
+            let style = if lossless { OutputStyle::Lossless } else { OutputStyle::Compact };
+            let report = file_reader.verify_files(&list, style);
+            for (file, result) in &report.files {
+                match result {
+                    Ok(None) => println!("[INFO] OK {:?}", file),
+                    Ok(Some(mismatch)) => println!("[INFO] DIFF {:?}: {mismatch}", file),
+                    Err(e) => eprintln!("[ERROR] Error verifying file {:?}: {e}", file),
+                }
+            }
+            println!("[INFO] {} ok, {} differ, {} failed", report.ok(), report.mismatched(), report.failed());
+            for (name, count) in report.by_type() {
+                println!("    {count:>5} {name}");
+            }
+            files_exit_code(report.mismatched() + report.failed())
+        }
+        Command::Search { input, type_name, predicates, json, lenient } => {
+            if type_name.is_none() && predicates.is_empty() {
+                return Err("search needs --type or --where".into())
+            }
+            let input = input.with_profile(&profile);
+            let (list, mut file_reader) = input.load(&profile, FileReader::new("".into(), input.root(), false, false, true, None))?;
+            file_reader.set_lenient(lenient);
+            if let Some(name) = &type_name
+                && RszDump::name_map().get(name).is_none() {
+                return Err(format!("{name} is not in the rsz dump").into())
+            }
+            let report = file_reader.search_files(&list, &Query { type_name, predicates });
+            for (file, result) in &report.files {
+                match result {
+                    Ok(matches) if json => {
+                        for m in matches {
+                            let mut line = serde_json::to_value(m)?;
+                            line["file"] = serde_json::json!(file);
+                            println!("{line}");
                         }
                     }
+                    Ok(matches) => {
+                        for m in matches {
+                            println!("{}: {m}", file.display());
+                        }
+                    }
+                    Err(e) => eprintln!("[ERROR] Error searching file {:?}: {e}", file),
                 }
             }
+            if !json {
+                println!("[INFO] {} matches in {} files, {} failed", report.matches(), report.matched_files(), report.failed());
+            }
+            files_exit_code(report.failed())
         }
-    }
-    results
+        Command::Info { input, json } => {
+            let input = input.with_profile(&profile);
+            let (list, file_reader) = input.load(&profile, FileReader::new("".into(), input.root(), false, false, true, None))?;
+            let mut failed = 0;
+            for file in &list {
+                match file_reader.info_file(file) {
+                    Ok(info) if json => println!("{}", serde_json::to_string(&info)?),
+                    Ok(info) => println!("{info}"),
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("[ERROR] Error reading file {:?}: {e}", file_reader.get_full_file_path(file));
+                    }
+                }
+            }
+            files_exit_code(failed)
+        }
+        Command::Save(command) => run_save(command, &profile)?,
+        Command::Hash(args) => run_hash(args, &profile)?,
+        Command::Sdk { input } => {
+            let input = input.with_profile(&profile);
+            let (list, mut file_reader) = input.load(&profile, FileReader::new("".into(), input.root(), true, false, true, None))?;
+            files_exit_code(file_reader.generate_sdk(&list)?)
+        }
+        Command::Types { input } => {
+            let input = input.with_profile(&profile);
+            let (list, mut file_reader) = input.load(&profile, FileReader::new("".into(), input.root(), false, false, true, None))?;
+            let (types, failed) = file_reader.collect_types(&list);
+            for hash in types {
+                match RszDump::get_struct(hash) {
+                    Ok(s) => println!("{hash:08x} {:08x} {}", s.crc, s.name),
+                    Err(_) => println!("{hash:08x} ???????? <unknown>"),
+                }
+            }
+            files_exit_code(failed)
+        }
+    };
+    Ok(code)
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     env_logger::init();
-    let args = Args::parse();
-    // Ugly but will change later
-    let rsz_file = std::env::var("RSZ_FILE").unwrap_or_else( 
-        |_| {
-            args.rsz
-        }
-    );
-    if !Path::new(&rsz_file).exists() {
-        eprintln!("BIG WARNING: {} not found", rsz_file);
-    } 
-    RSZ_FILE.set(rsz_file)?;
-
-    let enum_file = std::env::var("ENUM_FILE").unwrap_or_else(
-        |_| args.enums
-    );
-    if !Path::new(&enum_file).exists() {
-        eprintln!("BIG WARNING: {} not found", enum_file);
-    } 
-    ENUM_FILE.set(enum_file)?;
-    
+    let cli = Cli::parse();
+    // stdout carries the output when streaming
+    let stdin = cli.is_stdin();
     let now = SystemTime::now();
-    let mut list: Vec<PathBuf> = Vec::new();
-    if let Some(lf) = &args.list {
-        let lf = read_to_string(&lf).expect("Could not open list file");
-        for line in lf.lines() {
-            list.push(PathBuf::from(line));
+    let code = match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("[ERROR] {e}");
+            ExitCode::from(EXIT_FATAL)
         }
+    };
+    if !stdin {
+        eprintln!("Time taken: {} ms", now.elapsed().unwrap().as_millis());
     }
-
-    if let Some(f) = &args.file_name {
-        list.push(PathBuf::from(f));
-    }
-
-    let mut file_reader = FileReader::new(args.out_dir.into(), args.root_dir.map(|x| PathBuf::from(x)), args.dump_sdk, args.try_dump_rsz, true, args.steamid);
-    file_reader.dump_files(list)?;
-
-    println!("Time taken: {} ms", now.elapsed().unwrap().as_millis());
-    Ok(())
+    code
 }
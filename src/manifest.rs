@@ -0,0 +1,308 @@
+use std::{collections::BTreeMap, fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}, time::UNIX_EPOCH};
+
+use fasthash::{murmur3, FastHash};
+use serde::{Deserialize, Serialize};
+
+use crate::{format::FileFormat, game::GameProfile, reerr::{Result, ResultExt}, resource::OutputStyle};
+
+const MANIFEST_VERSION: u32 = 1;
+
+pub fn hash_bytes(data: &[u8]) -> String {
+    format!("{:032x}", murmur3::Hash128_x64::hash_with_seed(data, 0))
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    Ok(hash_bytes(&std::fs::read(path)?))
+}
+
+/// None if the file does not exist, read errors name the file
+fn hash_optional_file(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None)
+    }
+    hash_file(path).in_file(path).map(Some)
+}
+
+/// Size and modification time in nanoseconds, checked before hashing the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime: u64,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+        Ok(Self { size: metadata.len(), mtime })
+    }
+}
+
+/// Hashes of the schema files the rsz outputs were made with, None when the file is missing
+/// so formats that don't need them can still be dumped
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaStamp {
+    pub rsz: Option<String>,
+    pub enums: Option<String>,
+}
+
+impl SchemaStamp {
+    pub fn current() -> Result<Self> {
+        let game = GameProfile::active();
+        Ok(Self {
+            rsz: hash_optional_file(Path::new(&game.rsz_file))?,
+            enums: hash_optional_file(Path::new(&game.enum_file))?,
+        })
+    }
+}
+
+/// The streaming copy a texture or mesh was merged with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamingEntry {
+    pub size: u64,
+    pub mtime: u64,
+    pub hash: String,
+}
+
+impl StreamingEntry {
+    pub fn new(path: &Path) -> Result<Self> {
+        let stamp = FileStamp::read(path)?;
+        Ok(Self { size: stamp.size, mtime: stamp.mtime, hash: hash_file(path)? })
+    }
+
+    fn unchanged(&self, path: &Path) -> Result<bool> {
+        let stamp = FileStamp::read(path)?;
+        if stamp.size != self.size {
+            return Ok(false)
+        }
+        Ok(stamp.mtime == self.mtime || hash_file(path)? == self.hash)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub size: u64,
+    pub mtime: u64,
+    pub hash: String,
+    pub format: FileFormat,
+    #[serde(default)]
+    pub style: OutputStyle,
+    pub outputs: Vec<PathBuf>,
+    pub types: Vec<u32>,
+    /// The `natives/<platform>/streaming/` copy of the file if there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streaming: Option<StreamingEntry>,
+    /// Set when the schema changed since the outputs were written
+    #[serde(default)]
+    pub stale: bool,
+}
+
+impl ManifestEntry {
+    pub fn new(source: &Path, streaming: Option<&Path>, format: FileFormat, style: OutputStyle, outputs: Vec<PathBuf>, types: Vec<u32>) -> Result<Self> {
+        let stamp = FileStamp::read(source)?;
+        let streaming = match streaming {
+            Some(path) => Some(StreamingEntry::new(path).in_file(path)?),
+            None => None,
+        };
+        Ok(Self {
+            size: stamp.size,
+            mtime: stamp.mtime,
+            hash: hash_file(source)?,
+            format,
+            style,
+            outputs,
+            types,
+            streaming,
+            stale: false,
+        })
+    }
+
+    fn uses_schema(&self) -> bool {
+        self.format.has_rsz() || self.format == FileFormat::Save
+    }
+}
+
+/// Record of every dumped file in an output directory, used to skip unchanged inputs on the next run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub schema: SchemaStamp,
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            schema: SchemaStamp::default(),
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    pub const FILE_NAME: &'static str = "mhtame_manifest.json";
+
+    /// Loads the manifest of an output directory, a missing or outdated manifest gives an empty one
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(Self::FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        let manifest: Manifest = serde_json::from_slice(&std::fs::read(&path)?)?;
+        if manifest.version != MANIFEST_VERSION {
+            println!("[INFO] Manifest {:?} has version {}, dumping everything again", path, manifest.version);
+            return Ok(Self::default())
+        }
+        Ok(manifest)
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        let mut writer = BufWriter::new(File::create(output_dir.join(Self::FILE_NAME))?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Marks every rsz based output stale if the schema is not the one they were made with, returns how many were marked
+    pub fn set_schema(&mut self, schema: SchemaStamp) -> usize {
+        if self.schema == schema {
+            return 0
+        }
+        self.schema = schema;
+        let mut count = 0;
+        for entry in self.files.values_mut().filter(|e| e.uses_schema()) {
+            entry.stale = true;
+            count += 1;
+        }
+        count
+    }
+
+    pub fn key(source: &Path) -> String {
+        source.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Returns the entry if the source, its streaming copy and its outputs are unchanged, files are only hashed if the mtime changed
+    pub fn fresh_entry(&self, source: &Path, streaming: Option<&Path>, style: OutputStyle) -> Result<Option<ManifestEntry>> {
+        let Some(entry) = self.files.get(&Self::key(source)) else {
+            return Ok(None)
+        };
+        if entry.stale || entry.style != style || !entry.outputs.iter().all(|output| output.exists()) {
+            return Ok(None)
+        }
+        match (&entry.streaming, streaming) {
+            (None, None) => {}
+            (Some(old), Some(path)) if old.unchanged(path)? => {}
+            _ => return Ok(None),
+        }
+        let stamp = FileStamp::read(source)?;
+        if stamp.size != entry.size {
+            return Ok(None)
+        }
+        if stamp.mtime == entry.mtime {
+            return Ok(Some(entry.clone()))
+        }
+        if hash_file(source)? != entry.hash {
+            return Ok(None)
+        }
+        let mut entry = entry.clone();
+        entry.mtime = stamp.mtime;
+        Ok(Some(entry))
+    }
+
+    pub fn insert(&mut self, source: &Path, entry: ManifestEntry) {
+        self.files.insert(Self::key(source), entry);
+    }
+
+    pub fn remove(&mut self, source: &Path) {
+        self.files.remove(&Self::key(source));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    /// A source file and its output in a fresh directory
+    fn setup(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("mhtame_manifest_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.user.3");
+        let output = dir.join("a.user.3.json");
+        std::fs::write(&source, b"USR\0data").unwrap();
+        std::fs::write(&output, b"{}").unwrap();
+        (dir, source, output)
+    }
+
+    fn manifest(source: &Path, streaming: Option<&Path>, format: FileFormat, output: &Path) -> Manifest {
+        let mut manifest = Manifest::default();
+        let entry = ManifestEntry::new(source, streaming, format, OutputStyle::default(), vec![output.to_path_buf()], vec![]).unwrap();
+        manifest.insert(source, entry);
+        manifest
+    }
+
+    fn touch(path: &Path, data: &[u8]) {
+        std::fs::write(path, data).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options().write(true).open(path).unwrap().set_modified(later).unwrap();
+    }
+
+    #[test]
+    fn unchanged_file_is_fresh() {
+        let (dir, source, output) = setup("unchanged");
+        let manifest = manifest(&source, None, FileFormat::User, &output);
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_some());
+        // same bytes with a new mtime is still fresh after hashing
+        touch(&source, b"USR\0data");
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_or_missing_output_is_not_fresh() {
+        let (dir, source, output) = setup("changed");
+        let manifest = manifest(&source, None, FileFormat::User, &output);
+        touch(&source, b"USR\0datb");
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_none());
+        std::fs::write(&source, b"USR\0data").unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn schema_change_only_stales_rsz_outputs() {
+        let (dir, source, output) = setup("schema");
+        let tex = dir.join("b.tex.241106027");
+        std::fs::write(&tex, b"TEX\0").unwrap();
+        let mut manifest = manifest(&source, None, FileFormat::User, &output);
+        manifest.insert(&tex, ManifestEntry::new(&tex, None, FileFormat::Tex, OutputStyle::default(), vec![output.clone()], vec![]).unwrap());
+        assert_eq!(manifest.set_schema(SchemaStamp::default()), 0);
+        assert_eq!(manifest.set_schema(SchemaStamp { rsz: Some("1".to_string()), enums: None }), 1);
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_none());
+        assert!(manifest.fresh_entry(&tex, None, OutputStyle::default()).unwrap().is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streaming_copy_change_is_not_fresh() {
+        let (dir, source, output) = setup("streaming");
+        let streaming = dir.join("streaming.tex");
+        std::fs::write(&streaming, b"TEX\0mips").unwrap();
+        let manifest = manifest(&source, Some(&streaming), FileFormat::Tex, &output);
+        assert!(manifest.fresh_entry(&source, Some(&streaming), OutputStyle::default()).unwrap().is_some());
+        assert!(manifest.fresh_entry(&source, None, OutputStyle::default()).unwrap().is_none());
+        touch(&streaming, b"TEX\0mipz");
+        assert!(manifest.fresh_entry(&source, Some(&streaming), OutputStyle::default()).unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_schema_file_has_no_hash() {
+        assert_eq!(hash_optional_file(Path::new("does/not/exist.json")).unwrap(), None);
+    }
+}
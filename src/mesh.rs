@@ -1,5 +1,7 @@
 use crate::file_ext::*;
-use crate::reerr::{Result, FileParseError::*};
+use crate::format::FileFormat;
+use crate::reerr::{ErrorKind, Result, ReError};
+use crate::version::{Layout, VersionEntry};
 use std::io::{Read, Seek};
 
 pub type Vertex = [f32; 3];
@@ -105,6 +107,22 @@ impl VertexLayout {
     }
 }
 
+/// Byte range of a buffer kept in the streaming copy of the mesh, which holds the high detail lods
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamingRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl StreamingRange {
+    pub fn read<F: Read + Seek>(file: &mut F) -> Result<Self> {
+        Ok(Self {
+            start: file.read_u32()?,
+            end: file.read_u32()?,
+        })
+    }
+}
+
 #[allow(unused)]
 #[derive(Debug)]
 pub struct Mesh {
@@ -115,33 +133,55 @@ pub struct Mesh {
     pub total_buffer_size: u32,
     pub lod_groups: Vec<LodGroup>,
     pub names: Vec<String>,
+    pub streaming_ranges: Vec<StreamingRange>,
+    /// The buffers of the streaming ranges, once the streaming copy is merged
+    pub streaming_buffers: Vec<Vec<u8>>,
 }
 
 #[allow(unused_variables)]
 impl Mesh {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Whether part of the buffers live in a streaming copy of the file
+    pub fn has_streaming(&self) -> bool {
+        !self.streaming_ranges.is_empty()
+    }
+
+    /// Takes the buffers of the streaming ranges from the streaming copy, the data is the whole streaming file
+    pub fn merge_streaming(&mut self, data: &[u8]) -> Result<()> {
+        self.streaming_buffers = self.streaming_ranges.iter().map(|range| {
+            match data.get(range.start as usize..range.end as usize) {
+                Some(buffer) => Ok(buffer.to_vec()),
+                None => Err(ReError::new(ErrorKind::DataTooShort { len: data.len(), required: range.end as usize }).at(range.start as u64).into()),
+            }
+        }).collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
     pub fn new<F: Read + Seek>(mut file: F) -> Result<Self> {
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?;
-        if ext != "MESH" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("MESH"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"MESH" {
+            return Err(Box::new(ReError::magic(b"MESH", &magic).at(0)))
         }
         let version = file.read_u32()?;
+        let Layout::Mesh { sunbreak, sf6, wilds } = VersionEntry::layout(FileFormat::Mesh, version).map_err(|e| ReError::from_boxed(e).at(4))? else {
+            unreachable!("mesh versions have a mesh layout")
+        };
         let file_size = file.read_u32()?;
         let unkn_hash = file.read_u32()?;
         // read content flag
-        let wilds_unkn1 = file.read_u32()?;
+        let wilds_unkn1 = if wilds { file.read_u32()? } else { 0 };
         let name_count = file.read_u16()?;
         let content_flag = file.read_u16()?;
-        let sf6_unkn_count = file.read_u16()?;
+        let sf6_unkn_count = if sf6 { file.read_u16()? } else { 0 };
         //println!("version:{version} name_count:{name_count}, content_flag:{content_flag:b}");
 
-        let wilds_unkn2 = file.read_u32()?;
-        let wilds_unkn3 = file.read_u32()?;
-        let wilds_unkn4 = file.read_u32()?;
-        let wilds_unkn5 = file.read_u16()?;
+        let wilds_unkn2 = if wilds { file.read_u32()? } else { 0 };
+        let wilds_unkn3 = if wilds { file.read_u32()? } else { 0 };
+        let wilds_unkn4 = if wilds { file.read_u32()? } else { 0 };
+        let wilds_unkn5 = if wilds { file.read_u16()? } else { 0 };
 
         let vertices_offset = file.read_u64()?;
         let mesh_group_offset = file.read_u64()?;
@@ -150,7 +190,7 @@ impl Mesh {
         let normal_recalc_offset = file.read_u64()?;
         let blend_shape_offset = file.read_u64()?;
         let mesh_offset = file.read_u64()?;
-        let sf6_unkn1 = file.read_u64()?;
+        let sf6_unkn1 = if sf6 { file.read_u64()? } else { 0 };
         //println!("mesh_offset {mesh_offset:x}, vertices_offset {vertices_offset:x}");
         let floats_offset = file.read_u64()?;
         let aabb_offset = file.read_u64()?;
@@ -160,7 +200,7 @@ impl Mesh {
         let blend_shape_name_offset = file.read_u64()?;
         let name_offsets_offset = file.read_u64()?;
         let streaming_info_offset = file.read_u64()?;
-        let sf6_unkn4 = file.read_u64()?;
+        let sf6_unkn4 = if sf6 { file.read_u64()? } else { 0 };
 
         /*
          * read lods
@@ -192,7 +232,7 @@ impl Mesh {
         let vertex_element_offset = file.read_u64()?;
         let vertex_buffer_offset = file.read_u64()?;
 
-        let sunbreakoffset = file.read_u64()?;
+        let sunbreakoffset = if sunbreak { file.read_u64()? } else { 0 };
         let total_buffer_size = file.read_u32()?;
         let vertex_buffer_size = file.read_u32()?;
         let main_vertex_element_count = file.read_u16()?;
@@ -202,10 +242,10 @@ impl Mesh {
         //println!("PLEASE {unk}, {block_2_face_buffer_offset}, {main_vertex_element_count}");
         let vertex_element_size = file.read_u16()?;
         let unkn1 = file.read_u16()?;
-        let sunbreak_unk2 = file.read_u64()?;
-        let sf6_unkn0 = file.read_u64()?;
+        let sunbreak_unk2 = if sunbreak { file.read_u64()? } else { 0 };
+        let sf6_unkn0 = if sf6 { file.read_u64()? } else { 0 };
         let streaming_vertex_element_offset = file.read_u64()?;
-        let sf6_unkn2 = file.read_u64()?;
+        let sf6_unkn2 = if sf6 { file.read_u64()? } else { 0 };
 
         //read vertex elements
         file.seek(std::io::SeekFrom::Start(vertex_element_offset))?;
@@ -235,6 +275,20 @@ impl Mesh {
         }).collect::<Result<Vec<_>>>()?;
         //println!("{:?}", names);
 
+        /*
+         * Read the ranges of the streaming copy
+         */
+        let streaming_ranges = if streaming_info_offset != 0 {
+            file.seek(std::io::SeekFrom::Start(streaming_info_offset))?;
+            let range_count = file.read_u32()?;
+            let _unkn = file.read_u32()?;
+            let ranges_offset = file.read_u64()?;
+            file.seek(std::io::SeekFrom::Start(ranges_offset))?;
+            (0..range_count).map(|_| StreamingRange::read(&mut file)).collect::<Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
 
         Ok(Self {
             version,
@@ -244,6 +298,8 @@ impl Mesh {
             total_buffer_size,
             lod_groups,
             names,
+            streaming_ranges,
+            streaming_buffers: Vec::new(),
         })
     }
 }
@@ -133,6 +133,12 @@ pub struct Msg {
 
 impl DefaultDump for Msg {}
 
+impl Msg {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
+
 impl Serialize for Msg {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
         where
@@ -0,0 +1,385 @@
+use std::{collections::HashMap, fs::File, io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write}, path::Path, sync::Mutex};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use num_bigint::BigUint;
+
+use crate::{file_ext::ReadExt, reerr::{ErrorKind, ReError, Result, ResultExt}};
+
+/// Entry table is xored with a key that is stored rsa encrypted after it
+const FEATURE_ENCRYPTED_TABLE: u16 = 0x8;
+const ENCRYPTED_KEY_LEN: usize = 128;
+
+pub use crate::hash::path_hash;
+
+/// Rsa parameters of the key that encrypts the entry table, they differ by game
+#[derive(Debug, Clone)]
+pub struct PakKey {
+    pub modulus: BigUint,
+    pub exponent: BigUint,
+}
+
+impl PakKey {
+    /// Both numbers as big endian hex
+    pub fn from_hex(modulus: &str, exponent: &str) -> Result<Self> {
+        let parse = |s: &str| BigUint::parse_bytes(s.trim_start_matches("0x").as_bytes(), 16)
+            .ok_or_else(|| ReError::new(ErrorKind::InvalidValue(format!("Invalid hex number in pak key: {s}"))));
+        Ok(Self { modulus: parse(modulus)?, exponent: parse(exponent)? })
+    }
+
+    fn decrypt_table(&self, table: &mut [u8], encrypted_key: &[u8]) {
+        let mut key = BigUint::from_bytes_le(encrypted_key).modpow(&self.exponent, &self.modulus).to_bytes_le();
+        key.resize(32, 0);
+        for (i, byte) in table.iter_mut().enumerate() {
+            *byte ^= (i as u8).wrapping_add(key[i % 32].wrapping_mul(key[i % 29]));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    Zstd,
+}
+
+impl Compression {
+    fn flag(&self) -> u64 {
+        match self {
+            Self::None => 0,
+            Self::Deflate => 1,
+            Self::Zstd => 2,
+        }
+    }
+
+    /// None if compressing does not make the data smaller
+    fn compress(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let compressed = match self {
+            Self::None => return Ok(None),
+            Self::Deflate => {
+                let mut compressor = libdeflater::Compressor::new(libdeflater::CompressionLvl::default());
+                let mut out = vec![0; compressor.deflate_compress_bound(data.len())];
+                let len = compressor.deflate_compress(data, &mut out)
+                    .map_err(|e| ReError::new(ErrorKind::Other(format!("Could not deflate pak entry: {e}"))))?;
+                out.truncate(len);
+                out
+            }
+            Self::Zstd => zstd::bulk::compress(data, 0)?,
+        };
+        Ok((compressed.len() < data.len()).then_some(compressed))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PakEntry {
+    pub hash: u64,
+    pub offset: u64,
+    pub compressed_size: u64,
+    pub size: u64,
+    pub attributes: u64,
+    pub checksum: u64,
+}
+
+impl PakEntry {
+    pub fn compression(&self) -> Result<Compression> {
+        match self.attributes & 0xf {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Deflate),
+            2 => Ok(Compression::Zstd),
+            v => Err(ErrorKind::Unsupported(format!("Unknown pak compression {v}")).into()),
+        }
+    }
+
+    /// Resources encrypted on top of the table encryption
+    pub fn is_encrypted(&self) -> bool {
+        (self.attributes >> 16) & 0xff != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PakHeader {
+    pub major_version: u8,
+    pub minor_version: u8,
+    pub feature: u16,
+    pub file_count: u32,
+    pub fingerprint: u32,
+}
+
+/// A KPKA archive, entries are looked up by the hash of their path
+pub struct Pak<R> {
+    pub header: PakHeader,
+    pub entries: Vec<PakEntry>,
+    index: HashMap<u64, usize>,
+    // entries can be read from several threads
+    reader: Mutex<R>,
+}
+
+impl Pak<BufReader<File>> {
+    pub fn open(path: &Path, key: Option<&PakKey>) -> Result<Self> {
+        let file = File::open(path).map_err(|e| format!("Could not open pak {path:?}: {e}"))?;
+        Self::new(BufReader::new(file), key).in_file(path)
+    }
+}
+
+impl<R: Read + Seek> Pak<R> {
+    pub fn new(mut reader: R, key: Option<&PakKey>) -> Result<Self> {
+        let magic = reader.read_magic()?;
+        if &magic != b"KPKA" {
+            return Err(ReError::magic(b"KPKA", &magic).at(0).into())
+        }
+        let header = PakHeader {
+            major_version: reader.read_u8()?,
+            minor_version: reader.read_u8()?,
+            feature: reader.read_u16()?,
+            file_count: reader.read_u32()?,
+            fingerprint: reader.read_u32()?,
+        };
+        let entry_size = match (header.major_version, header.minor_version) {
+            (2, 0) => 24,
+            (4, 0) | (4, 1) => 48,
+            (major, minor) => return Err(ReError::new(ErrorKind::UnsupportedVersion {
+                format: "PAK",
+                version: (major as u32) << 8 | minor as u32,
+            }).at(4).into()),
+        };
+
+        let mut table = vec![0; header.file_count as usize * entry_size];
+        reader.read_exact(&mut table)?;
+        if header.feature & FEATURE_ENCRYPTED_TABLE != 0 {
+            let Some(key) = key else {
+                return Err(ErrorKind::Decrypt("The pak entry table is encrypted, set the pak key in the profile".to_string()).into())
+            };
+            let mut encrypted_key = [0; ENCRYPTED_KEY_LEN];
+            reader.read_exact(&mut encrypted_key)?;
+            key.decrypt_table(&mut table, &encrypted_key);
+        }
+
+        let mut table = Cursor::new(table);
+        let entries = (0..header.file_count).map(|_| {
+            if header.major_version == 2 {
+                let offset = table.read_u64()?;
+                let size = table.read_u64()?;
+                let lower = table.read_u32()? as u64;
+                let upper = table.read_u32()? as u64;
+                Ok(PakEntry { hash: upper << 32 | lower, offset, compressed_size: size, size, attributes: 0, checksum: 0 })
+            } else {
+                let lower = table.read_u32()? as u64;
+                let upper = table.read_u32()? as u64;
+                Ok(PakEntry {
+                    hash: upper << 32 | lower,
+                    offset: table.read_u64()?,
+                    compressed_size: table.read_u64()?,
+                    size: table.read_u64()?,
+                    attributes: table.read_u64()?,
+                    checksum: table.read_u64()?,
+                })
+            }
+        }).collect::<Result<Vec<_>>>()?;
+
+        let index = entries.iter().enumerate().map(|(i, entry)| (entry.hash, i)).collect();
+        Ok(Self { header, entries, index, reader: Mutex::new(reader) })
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&PakEntry> {
+        self.index.get(&path_hash(path)).map(|&i| &self.entries[i])
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.index.contains_key(&path_hash(path))
+    }
+
+    /// Reads and decompresses the entry of a path
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self.entry(path)
+            .ok_or_else(|| format!("{path} is not in the pak"))?;
+        self.read_entry(entry)
+    }
+
+    pub fn read_entry(&self, entry: &PakEntry) -> Result<Vec<u8>> {
+        if entry.is_encrypted() {
+            return Err(ErrorKind::Unsupported(format!("Encrypted pak entry {:016x}", entry.hash)).into())
+        }
+        let mut data = vec![0; entry.compressed_size as usize];
+        {
+            let mut reader = self.reader.lock().unwrap();
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            reader.read_exact(&mut data)?;
+        }
+        let data = match entry.compression()? {
+            // some entries are flagged compressed but stored as is
+            _ if entry.compressed_size == entry.size => data,
+            Compression::None => data,
+            Compression::Deflate => {
+                let mut out = vec![0; entry.size as usize];
+                libdeflater::Decompressor::new().deflate_decompress(&data, &mut out)
+                    .map_err(|e| ReError::new(ErrorKind::Other(format!("Could not inflate pak entry: {e}"))).at(entry.offset))?;
+                out
+            }
+            Compression::Zstd => zstd::bulk::decompress(&data, entry.size as usize)
+                .map_err(|e| ReError::new(ErrorKind::Other(format!("Could not decompress pak entry: {e}"))).at(entry.offset))?,
+        };
+        Ok(data)
+    }
+
+    /// Names of the entries found in a list of paths, in the order of the list
+    pub fn resolve<'a>(&self, paths: &'a PathList) -> Vec<&'a str> {
+        paths.paths.iter().filter(|path| self.contains(path)).map(|path| path.as_str()).collect()
+    }
+
+    /// Entries whose path is not in the list
+    pub fn unresolved<'a>(&'a self, paths: &PathList) -> impl Iterator<Item = &'a PakEntry> {
+        let known: std::collections::HashSet<u64> = paths.paths.iter().map(|path| path_hash(path)).collect();
+        self.entries.iter().filter(move |entry| !known.contains(&entry.hash))
+    }
+}
+
+/// Writes a version 4.0 pak with an unencrypted entry table
+#[derive(Default)]
+pub struct PakWriter {
+    files: Vec<(String, Vec<u8>)>,
+    compression: Compression,
+}
+
+impl PakWriter {
+    const HEADER_SIZE: u64 = 16;
+    const ENTRY_SIZE: u64 = 48;
+
+    pub fn new(compression: Compression) -> Self {
+        Self { files: Vec::new(), compression }
+    }
+
+    /// Adds a file under its path inside the pak, like `natives/STM/GameDesign/Common/Item/ItemData.user.3`
+    pub fn add(&mut self, path: &str, data: Vec<u8>) {
+        self.files.push((path.replace('\\', "/"), data));
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut entries = Vec::with_capacity(self.files.len());
+        let mut blobs = Vec::with_capacity(self.files.len());
+        let mut offset = Self::HEADER_SIZE + Self::ENTRY_SIZE * self.files.len() as u64;
+        for (path, data) in &self.files {
+            let (attributes, blob) = match self.compression.compress(data)? {
+                Some(compressed) => (self.compression.flag(), compressed),
+                None => (0, data.clone()),
+            };
+            entries.push(PakEntry {
+                hash: path_hash(path),
+                offset,
+                compressed_size: blob.len() as u64,
+                size: data.len() as u64,
+                attributes,
+                checksum: 0,
+            });
+            offset += blob.len() as u64;
+            blobs.push(blob);
+        }
+
+        writer.write_all(b"KPKA")?;
+        writer.write_u8(4)?;
+        writer.write_u8(0)?;
+        writer.write_u16::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(entries.len() as u32)?;
+        writer.write_u32::<LittleEndian>(0)?;
+        for entry in &entries {
+            writer.write_u32::<LittleEndian>(entry.hash as u32)?;
+            writer.write_u32::<LittleEndian>((entry.hash >> 32) as u32)?;
+            writer.write_u64::<LittleEndian>(entry.offset)?;
+            writer.write_u64::<LittleEndian>(entry.compressed_size)?;
+            writer.write_u64::<LittleEndian>(entry.size)?;
+            writer.write_u64::<LittleEndian>(entry.attributes)?;
+            writer.write_u64::<LittleEndian>(entry.checksum)?;
+        }
+        for blob in &blobs {
+            writer.write_all(blob)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Paths to look up in paks, one per line like `natives/STM/GameDesign/Common/Item/ItemData.user.3`
+#[derive(Debug, Clone, Default)]
+pub struct PathList {
+    pub paths: Vec<String>,
+}
+
+impl PathList {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("Could not open path list {path:?}: {e}"))?;
+        Ok(Self {
+            paths: data.lines()
+                .map(|line| line.trim().replace('\\', "/"))
+                .filter(|line| !line.is_empty())
+                .collect(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for line in &self.paths {
+            writeln!(writer, "{line}")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(compression: Compression) {
+        let compressible = b"ItemData".repeat(512);
+        let tiny = vec![1, 2, 3];
+        let mut writer = PakWriter::new(compression);
+        writer.add("natives/STM/GameDesign/Common/Item/ItemData.user.3", compressible.clone());
+        writer.add("natives\\STM\\tiny.user.3", tiny.clone());
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let pak = Pak::new(Cursor::new(buf), None).unwrap();
+        assert_eq!(pak.read("natives/STM/GameDesign/Common/Item/ItemData.user.3").unwrap(), compressible);
+        assert_eq!(pak.read("natives/STM/tiny.user.3").unwrap(), tiny);
+
+        let entry = pak.entry("natives/STM/GameDesign/Common/Item/ItemData.user.3").unwrap();
+        assert_eq!(entry.compression().unwrap(), compression);
+        if compression != Compression::None {
+            assert!(entry.compressed_size < entry.size);
+        }
+        // data that does not shrink is stored as is
+        assert_eq!(pak.entry("natives/STM/tiny.user.3").unwrap().compression().unwrap(), Compression::None);
+    }
+
+    #[test]
+    fn round_trip_uncompressed() {
+        round_trip(Compression::None);
+    }
+
+    #[test]
+    fn round_trip_deflate() {
+        round_trip(Compression::Deflate);
+    }
+
+    #[test]
+    fn round_trip_zstd() {
+        round_trip(Compression::Zstd);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::file_ext::*;
 use crate::rsz::{Rsz, rszserde::StringU16};
-use crate::reerr::{Result, FileParseError::*};
+use crate::reerr::{Result, ReError};
 use std::io::{Read, Seek};
 use file_macros::StructRW;
 use nalgebra_glm::Vec4;
@@ -36,14 +36,14 @@ pub struct PogNode {
 }
 
 impl Pog {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     pub fn new<F: Read + Seek>(mut file: F) -> Result<Pog> {
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?;
-        if ext != "POG\0" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("POG"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"POG\0" {
+            return Err(Box::new(ReError::magic(b"POG\0", &magic).at(0)))
         }
 
         let version = file.read_u32()?;
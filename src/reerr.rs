@@ -1,55 +1,243 @@
 use std::error::Error;
 use std::fmt;
+use std::path::{Path, PathBuf};
 pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// Every way parsing or writing a file can fail
 #[derive(Debug)]
-pub enum FileParseError {
-    MagicError { real_magic: String, read_magic: String },
-    TexReadError { source: String },
-    GDeflateError { source: String },
-    Unsupported { source: String },
+pub enum ErrorKind {
+    Magic { expected: String, found: String },
+    UnsupportedVersion { format: &'static str, version: u32 },
+    UnsupportedFileVersion { format: &'static str, suffix: Option<u32>, header: Option<u32> },
+    NonZeroPadding { what: &'static str, value: u64 },
     InvalidBool(u8),
-    BadAlign(u64, u64),
-    InvalidRszTypeHash(u32),
-    DataTooShort(usize, usize), // slice len, required size
+    BadAlign { align: u64 },
+    /// A fixed size value read more bytes than its field has
+    FieldOverrun { size: u64, read: u64 },
+    DataTooShort { len: usize, required: usize },
+    UnknownFormat { magic: Vec<u8> },
+    UnknownRszHash(u32),
+    /// The end of an instance of an unknown type could not be found, since instance `next` after it is of an unknown
+    /// type too or fits at none of the offsets tried
+    UnknownRszExtent { hash: u32, idx: u32, next: u32 },
+    InvalidObjectIndex { idx: u32, hash: u32 },
+    UnsetDeserializer(String),
+    UnsetSerializer(String),
+    MissingFieldDescription,
+    UnknownFieldType(String),
+    InvalidSlot(String),
+    InvalidValue(String),
+    InvalidPath { path: String, reason: String },
+    TexRead(String),
+    UnsupportedTexFormat(u32),
+    GDeflate(String),
+    Decrypt(String),
+    Unsupported(String),
+    Io(std::io::Error),
+    /// Errors from other libraries, kept as a message so ReError stays Send
+    Other(String),
 }
 
-impl Error for FileParseError {}
+impl ErrorKind {
+    /// Stable name of the kind, meant for grouping and matching failures
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::Magic { .. } => "magic_mismatch",
+            Self::UnsupportedVersion { .. } => "unsupported_version",
+            Self::UnsupportedFileVersion { .. } => "unsupported_file_version",
+            Self::NonZeroPadding { .. } => "non_zero_padding",
+            Self::InvalidBool(_) => "invalid_bool",
+            Self::BadAlign { .. } => "bad_align",
+            Self::FieldOverrun { .. } => "field_overrun",
+            Self::DataTooShort { .. } => "data_too_short",
+            Self::UnknownFormat { .. } => "unknown_format",
+            Self::UnknownRszHash(_) => "unknown_rsz_hash",
+            Self::UnknownRszExtent { .. } => "unknown_rsz_extent",
+            Self::InvalidObjectIndex { .. } => "invalid_object_index",
+            Self::UnsetDeserializer(_) => "unset_deserializer",
+            Self::UnsetSerializer(_) => "unset_serializer",
+            Self::MissingFieldDescription => "missing_field_description",
+            Self::UnknownFieldType(_) => "unknown_field_type",
+            Self::InvalidSlot(_) => "invalid_slot",
+            Self::InvalidValue(_) => "invalid_value",
+            Self::InvalidPath { .. } => "invalid_path",
+            Self::TexRead(_) => "tex_read",
+            Self::UnsupportedTexFormat(_) => "unsupported_tex_format",
+            Self::GDeflate(_) => "gdeflate",
+            Self::Decrypt(_) => "decrypt",
+            Self::Unsupported(_) => "unsupported",
+            Self::Io(_) => "io",
+            Self::Other(_) => "other",
+        }
+    }
+}
 
-impl fmt::Display for FileParseError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::MagicError { real_magic, read_magic } => write!(f, "File magic does not match. Should Be: {}, Is => {}", real_magic, read_magic),
-            Self::TexReadError { source } => write!(f, "{}", source),
-            Self::GDeflateError { source } => write!(f, "{}", source),
-            Self::Unsupported { source } => write!(f, "{}", source),
+            Self::Magic { expected, found } => write!(f, "File magic does not match. Should Be: {}, Is => {}", expected, found),
+            Self::UnsupportedVersion { format, version } => write!(f, "Unexpected {} version {:#x}", format, version),
+            Self::UnsupportedFileVersion { format, suffix, header } => {
+                write!(f, "Unsupported {} version", format)?;
+                if let Some(suffix) = suffix {
+                    write!(f, ", file name suffix .{}", suffix)?;
+                }
+                if let Some(header) = header {
+                    write!(f, ", header version {}", header)?;
+                }
+                Ok(())
+            }
+            Self::NonZeroPadding { what, value } => write!(f, "Unexpected non-zero padding in {}: {}", what, value),
             Self::InvalidBool(v) => write!(f, "Invalid value {} for bool", *v),
-            Self::BadAlign(pos, align) => write!(f, "Non-zero padding with pos:{:08X}, align:{:08X}", *pos, *align),
-            Self::InvalidRszTypeHash(v) => write!(f, "Invalid type hash {:08X} not found in rsz", *v),
-            Self::DataTooShort(slice_len, size) => write!(f, "Slice too short with len {:08X}, required {:08X} bytes", *slice_len, *size),
+            Self::BadAlign { align } => write!(f, "Non-zero padding with align:{:08X}", *align),
+            Self::FieldOverrun { size, read } => write!(f, "Read {read:#x} bytes for a field of size {size:#x}"),
+            Self::DataTooShort { len, required } => write!(f, "Slice too short with len {:08X}, required {:08X} bytes", *len, *required),
+            Self::UnknownFormat { magic } => write!(f, "Could not detect format from magic {:02X?} or file name", magic),
+            Self::UnknownRszHash(v) => write!(f, "Invalid type hash {:08X} not found in rsz", *v),
+            Self::UnknownRszExtent { hash, idx, next } => write!(f, "Could not find where instance {idx} of unknown type {hash:08X} ends, instance {next} after it can't be placed"),
+            Self::InvalidObjectIndex { idx, hash } => write!(f, "Invalid Object Index {} for hash {:08X}", *idx, *hash),
+            Self::UnsetDeserializer(v) => write!(f, "Deserializer not set for {}", v),
+            Self::UnsetSerializer(v) => write!(f, "Serializer not set for {}", v),
+            Self::MissingFieldDescription => write!(f, "Missing field description"),
+            Self::UnknownFieldType(v) => write!(f, "Unknown field type {}", v),
+            Self::InvalidSlot(v) => write!(f, "Invalid extern slot: {}", v),
+            Self::InvalidValue(v) => write!(f, "{}", v),
+            Self::InvalidPath { path, reason } => write!(f, "Invalid path {path}: {reason}"),
+            Self::TexRead(v) => write!(f, "{}", v),
+            Self::UnsupportedTexFormat(v) => write!(f, "unsupported tex format {:08X}", *v),
+            Self::GDeflate(v) => write!(f, "{}", v),
+            Self::Decrypt(v) => write!(f, "{}", v),
+            Self::Unsupported(v) => write!(f, "{}", v),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Other(e) => write!(f, "{}", e),
         }
     }
 }
 
+/// Error returned by the parsers, the kind plus where it happened
 #[derive(Debug)]
-pub enum RszError {
-    UnsetDeserializer(String),
-    UnsetSerializer(String),
-    InvalidRszTypeHash(u32),
-    InvalidRszObjectIndex(u32, u32),
-    MissingFieldDescription(String),
+pub struct ReError {
+    pub kind: ErrorKind,
+    pub offset: Option<u64>,
+    pub file: Option<PathBuf>,
+    /// Path of the rsz field being read, like `app.user_data.ItemData._Values[12]._Name`
+    pub field_path: Option<String>,
+}
+
+impl ReError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self { kind, offset: None, file: None, field_path: None }
+    }
+
+    pub fn magic(expected: &[u8], found: &[u8]) -> Self {
+        Self::new(ErrorKind::Magic {
+            expected: String::from_utf8_lossy(expected).to_string(),
+            found: String::from_utf8_lossy(found).to_string(),
+        })
+    }
+
+    pub fn at(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn in_file(mut self, file: &Path) -> Self {
+        self.file = Some(file.to_path_buf());
+        self
+    }
+
+    pub fn field(mut self, field_path: &str) -> Self {
+        self.push_field(field_path);
+        self
+    }
+
+    /// Fields are added from the innermost one outwards, so the name is prepended
+    fn push_field(&mut self, name: &str) {
+        self.field_path = Some(match self.field_path.take() {
+            Some(inner) if inner.starts_with('[') => format!("{name}{inner}"),
+            Some(inner) => format!("{name}.{inner}"),
+            None => name.to_string(),
+        });
+    }
+
+    pub fn category(&self) -> &'static str {
+        self.kind.category()
+    }
+
+    /// Turns any error into a ReError so context can be attached to it
+    pub fn from_boxed(e: Box<dyn Error>) -> Box<ReError> {
+        let e = match e.downcast::<ReError>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        match e.downcast::<std::io::Error>() {
+            Ok(e) => Box::new(ReError::new(ErrorKind::Io(*e))),
+            Err(e) => Box::new(ReError::new(ErrorKind::Other(e.to_string()))),
+        }
+    }
+
+    /// Finds the ReError behind a boxed error, for matching on the kind
+    pub fn find<'a>(e: &'a (dyn Error + 'static)) -> Option<&'a ReError> {
+        e.downcast_ref::<ReError>()
+    }
+}
+
+impl Error for ReError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
 }
-impl Error for RszError {}
 
-impl fmt::Display for RszError {
+impl fmt::Display for ReError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::InvalidRszTypeHash(v) => write!(f, "Invalid type hash {:08X} not found in rsz", *v),
-            Self::UnsetDeserializer(v) => write!(f, "Deserializer not set for {}", *v,),
-            Self::UnsetSerializer(v) => write!(f, "Deserializer not set for {}", *v),
-            Self::InvalidRszObjectIndex(i, h) => write!(f, "Invalid Object Index {} for hash {}", *i, *h),
-            Self::MissingFieldDescription(source) => write!(f, "Missing field description when reading data at {}", source),
+        write!(f, "{}", self.kind)?;
+        if let Some(field_path) = &self.field_path {
+            write!(f, " in field {}", field_path)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at {:#x}", offset)?;
         }
+        Ok(())
     }
 }
 
+impl From<ErrorKind> for ReError {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<ErrorKind> for Box<dyn Error> {
+    fn from(kind: ErrorKind) -> Self {
+        Box::new(ReError::new(kind))
+    }
+}
+
+/// Attaches context to errors on their way up
+pub trait ResultExt<T> {
+    fn in_file(self, file: &Path) -> Result<T>;
+    fn field(self, name: &str) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn in_file(self, file: &Path) -> Result<T> {
+        self.map_err(|e| {
+            let mut e = ReError::from_boxed(e);
+            if e.file.is_none() {
+                e.file = Some(file.to_path_buf());
+            }
+            e as Box<dyn Error>
+        })
+    }
+
+    fn field(self, name: &str) -> Result<T> {
+        self.map_err(|e| {
+            let mut e = ReError::from_boxed(e);
+            e.push_field(name);
+            e as Box<dyn Error>
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, error::Error, fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}, time::Duration};
+
+use serde::Serialize;
+
+use crate::{format::FileFormat, reerr::{ReError, Result}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// One json document with every file and the summary
+    #[default]
+    Json,
+    /// One json object per line, the summary is the last line
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Ok,
+    /// Unchanged since the last run, the outputs were kept
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Written as the extension like the summary keys
+    #[serde(serialize_with = "serialize_format")]
+    pub format: Option<FileFormat>,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_category: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub outputs: Vec<PathBuf>,
+    pub duration_ms: f64,
+}
+
+impl FileReport {
+    pub fn ok(path: PathBuf, format: FileFormat, outputs: Vec<PathBuf>, duration: Duration) -> Self {
+        Self {
+            path,
+            format: Some(format),
+            status: FileStatus::Ok,
+            error_category: None,
+            error: None,
+            outputs,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }
+    }
+
+    pub fn skipped(path: PathBuf, format: FileFormat, outputs: Vec<PathBuf>, duration: Duration) -> Self {
+        Self {
+            status: FileStatus::Skipped,
+            ..Self::ok(path, format, outputs, duration)
+        }
+    }
+
+    pub fn failed(path: PathBuf, format: Option<FileFormat>, error: &(dyn Error + 'static), duration: Duration) -> Self {
+        Self {
+            path,
+            format,
+            status: FileStatus::Failed,
+            error_category: Some(error_category(error)),
+            error: Some(error.to_string()),
+            outputs: Vec::new(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatusCount {
+    pub ok: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportSummary {
+    pub total: usize,
+    pub ok: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub duration_ms: f64,
+    pub by_format: BTreeMap<String, StatusCount>,
+    pub by_error: BTreeMap<&'static str, usize>,
+}
+
+/// Per file results of a batch run, in the same order as the input list
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+    pub duration: Duration,
+}
+
+impl Report {
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|f| f.status == FileStatus::Failed).count()
+    }
+
+    pub fn summary(&self) -> ReportSummary {
+        let mut summary = ReportSummary {
+            total: self.files.len(),
+            duration_ms: self.duration.as_secs_f64() * 1000.0,
+            ..Default::default()
+        };
+        for file in &self.files {
+            let format = file.format.map(|f| f.to_string()).unwrap_or_else(|| "unknown".to_string());
+            let count = summary.by_format.entry(format).or_default();
+            match file.status {
+                FileStatus::Ok => {
+                    summary.ok += 1;
+                    count.ok += 1;
+                }
+                FileStatus::Skipped => {
+                    summary.skipped += 1;
+                    count.skipped += 1;
+                }
+                FileStatus::Failed => {
+                    summary.failed += 1;
+                    count.failed += 1;
+                    *summary.by_error.entry(file.error_category.unwrap_or("other")).or_default() += 1;
+                }
+            }
+        }
+        summary
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Json => {
+                #[derive(Serialize)]
+                struct Wrapped<'a> {
+                    files: &'a [FileReport],
+                    summary: ReportSummary,
+                }
+                serde_json::to_writer_pretty(&mut *writer, &Wrapped { files: &self.files, summary: self.summary() })?;
+                writeln!(writer)?;
+            }
+            ReportFormat::Ndjson => {
+                for file in &self.files {
+                    serde_json::to_writer(&mut *writer, file)?;
+                    writeln!(writer)?;
+                }
+                #[derive(Serialize)]
+                struct Wrapped {
+                    summary: ReportSummary,
+                }
+                serde_json::to_writer(&mut *writer, &Wrapped { summary: self.summary() })?;
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path, format: ReportFormat) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+fn serialize_format<S: serde::Serializer>(format: &Option<FileFormat>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match format {
+        Some(format) => serializer.collect_str(format),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Stable name for the kind of error, used to group failures in reports
+pub fn error_category(error: &(dyn Error + 'static)) -> &'static str {
+    if let Some(e) = ReError::find(error) {
+        e.category()
+    } else if error.is::<std::io::Error>() {
+        "io"
+    } else if error.is::<serde_json::Error>() {
+        "json"
+    } else {
+        "other"
+    }
+}
@@ -0,0 +1,536 @@
+use std::{collections::BTreeSet, fs::File, io::{BufWriter, Cursor, Read, Seek, Write}, path::{Path, PathBuf}};
+
+use image::{codecs::png::PngEncoder, ImageEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    edit::EditableFile,
+    file::StructRW,
+    font::Oft,
+    format::FileFormat,
+    mesh::Mesh,
+    msg::Msg,
+    pog::{Pog, PogList, PogNode, PogPoint},
+    reerr::{ErrorKind, Result, ResultExt},
+    rsz::Rsz,
+    save::{types::to_dersz, SaveContext, SaveFile},
+    scn::Scn,
+    tdb::TDBHeader,
+    tex::Tex,
+    user::{User, UserChild},
+    version::VersionEntry,
+    vfs::Vfs,
+};
+
+/// Where a resource is loaded from, the name is used when the magic is not recognized
+pub enum Source {
+    Path(PathBuf),
+    Bytes { data: Vec<u8>, name: String },
+}
+
+impl Source {
+    pub fn reader<R: Read>(mut reader: R, name: &str) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(Self::Bytes { data, name: name.to_string() })
+    }
+
+    /// The file on disk if the vfs has one, otherwise its bytes
+    pub fn vfs(vfs: &dyn Vfs, path: &str) -> Result<Self> {
+        match vfs.disk_path(path) {
+            Some(disk_path) => Ok(Self::Path(disk_path)),
+            None => Ok(Self::Bytes {
+                data: vfs.read(path)?,
+                name: path.rsplit('/').next().unwrap_or_default().to_string(),
+            }),
+        }
+    }
+}
+
+impl From<&Path> for Source {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl From<&PathBuf> for Source {
+    fn from(path: &PathBuf) -> Self {
+        Self::Path(path.clone())
+    }
+}
+
+impl From<PathBuf> for Source {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+/// How json exports are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStyle {
+    #[default]
+    Pretty,
+    Compact,
+    /// One rsz root object per line for user and save files, other files are a single line
+    Ndjson,
+    /// Pretty, with the raw value of enums next to their name so nothing is lost
+    Lossless,
+}
+
+impl OutputStyle {
+    pub fn write<W: Write, T: Serialize + ?Sized>(&self, writer: &mut W, value: &T) -> Result<()> {
+        match self {
+            Self::Pretty | Self::Lossless => serde_json::to_writer_pretty(&mut *writer, value)?,
+            Self::Compact => serde_json::to_writer(&mut *writer, value)?,
+            Self::Ndjson => {
+                serde_json::to_writer(&mut *writer, value)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Key for save files
+    pub steamid: Option<u64>,
+    /// Keep instances of types missing from the rsz dump as raw bytes
+    pub lenient: bool,
+}
+
+/// Any file the library can read
+pub enum Resource {
+    User(User),
+    Scn(Scn),
+    Pog(Pog),
+    PogList(PogList),
+    Rsz(Rsz),
+    Tex(Tex),
+    Msg(Msg),
+    Mesh(Mesh),
+    Oft(Oft),
+    Save(SaveFile),
+    Exe(TDBHeader),
+}
+
+impl Resource {
+    pub fn load(source: impl Into<Source>, options: &LoadOptions) -> Result<Self> {
+        match source.into() {
+            Source::Path(path) => {
+                let mut data = Vec::new();
+                File::open(&path)?.read_to_end(&mut data)?;
+                let name = path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+                Self::from_reader(Cursor::new(data), &name, options).in_file(&path)
+            }
+            Source::Bytes { data, name } => Self::from_reader(Cursor::new(data), &name, options),
+        }
+    }
+
+    /// Loads a file inside a vfs, textures and meshes also get their streaming copy merged if the vfs has one
+    pub fn load_from_vfs(vfs: &dyn Vfs, path: &str, options: &LoadOptions) -> Result<Self> {
+        let mut resource = Self::load(Source::vfs(vfs, path)?, options)?;
+        if !resource.has_streaming() {
+            return Ok(resource)
+        }
+        if let Some(streaming) = streaming_path(path)
+            && vfs.contains(&streaming) {
+            log::info!("Merging Streaming File: {streaming}");
+            resource.merge_streaming(vfs.read(&streaming)?).in_file(Path::new(&streaming))?;
+        }
+        Ok(resource)
+    }
+
+    pub fn from_reader<R: Read + Seek>(mut reader: R, name: &str, options: &LoadOptions) -> Result<Self> {
+        let format = FileFormat::detect(&mut reader, name)?;
+        let resource = Self::read_format(reader, format, options)?;
+        resource.check_version(FileFormat::version_from_file_name(name))?;
+        Ok(resource)
+    }
+
+    /// Checks the file name suffix and header version are a combination we know
+    pub fn check_version(&self, suffix: Option<u32>) -> Result<()> {
+        VersionEntry::find(self.format(), suffix, self.header_version())?;
+        Ok(())
+    }
+
+    /// Version in the file header, for the formats that have one
+    pub fn header_version(&self) -> Option<u32> {
+        match self {
+            Self::Pog(pog) => Some(pog.version()),
+            Self::Rsz(rsz) => Some(rsz.version()),
+            Self::Tex(tex) => Some(tex.version()),
+            Self::Msg(msg) => Some(msg.version()),
+            Self::Mesh(mesh) => Some(mesh.version()),
+            _ => None,
+        }
+    }
+
+    /// Skips detection, for when the format is already known
+    pub fn read_format<R: Read + Seek>(mut reader: R, format: FileFormat, options: &LoadOptions) -> Result<Self> {
+        let mut resource = match format {
+            FileFormat::User => Self::User(User::new(reader)?),
+            FileFormat::Scn => Self::Scn(Scn::new(reader)?),
+            FileFormat::Pog => Self::Pog(Pog::new(reader)?),
+            FileFormat::PogList => Self::PogList(PogList::read(&mut reader, &mut ())?),
+            FileFormat::Rsz => Self::Rsz(Rsz::new(&mut reader, 0, 0)?),
+            FileFormat::Tex => Self::Tex(Tex::new(reader)?),
+            FileFormat::Msg => Self::Msg(Msg::read(&mut reader, &mut ())?),
+            FileFormat::Mesh => Self::Mesh(Mesh::new(reader)?),
+            FileFormat::Oft => Self::Oft(Oft::new(reader)?),
+            FileFormat::Save => {
+                let Some(key) = options.steamid else {
+                    return Err("Can only decrypt save files with steamid.\nGo here to find it https://help.steampowered.com/en/faqs/view/2816-BE67-5B69-0FEC".into())
+                };
+                Self::Save(SaveFile::read(&mut reader, &mut SaveContext { key })?)
+            }
+            FileFormat::Exe => Self::Exe(TDBHeader::from_exe(&mut reader)?),
+        };
+        for rsz in resource.rszs_mut() {
+            rsz.lenient = options.lenient;
+        }
+        Ok(resource)
+    }
+
+    pub fn format(&self) -> FileFormat {
+        match self {
+            Self::User(_) => FileFormat::User,
+            Self::Scn(_) => FileFormat::Scn,
+            Self::Pog(_) => FileFormat::Pog,
+            Self::PogList(_) => FileFormat::PogList,
+            Self::Rsz(_) => FileFormat::Rsz,
+            Self::Tex(_) => FileFormat::Tex,
+            Self::Msg(_) => FileFormat::Msg,
+            Self::Mesh(_) => FileFormat::Mesh,
+            Self::Oft(_) => FileFormat::Oft,
+            Self::Save(_) => FileFormat::Save,
+            Self::Exe(_) => FileFormat::Exe,
+        }
+    }
+
+    /// Whether the resource can have a streaming copy to merge
+    pub fn has_streaming(&self) -> bool {
+        match self {
+            Self::Tex(_) => true,
+            Self::Mesh(mesh) => mesh.has_streaming(),
+            _ => false,
+        }
+    }
+
+    /// Merges the streaming copy of a texture or mesh, the data is the whole streaming file
+    pub fn merge_streaming(&mut self, data: Vec<u8>) -> Result<()> {
+        match self {
+            Self::Tex(tex) => tex.merge_streaming(Tex::new(Cursor::new(data))?)?,
+            Self::Mesh(mesh) => mesh.merge_streaming(&data)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Every rsz block in the resource
+    pub fn rszs(&self) -> Vec<&Rsz> {
+        match self {
+            Self::User(user) => vec![&user.rsz],
+            Self::Scn(scn) => vec![&scn.rsz],
+            Self::Pog(pog) => pog.rszs.iter().collect(),
+            Self::Rsz(rsz) => vec![rsz],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn rszs_mut(&mut self) -> Vec<&mut Rsz> {
+        match self {
+            Self::User(user) => vec![&mut user.rsz],
+            Self::Scn(scn) => vec![&mut scn.rsz],
+            Self::Pog(pog) => pog.rszs.iter_mut().collect(),
+            Self::Rsz(rsz) => vec![rsz],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Hashes of the rsz types used by the resource
+    pub fn types(&self) -> BTreeSet<u32> {
+        self.rszs().iter()
+            .flat_map(|rsz| rsz.type_descriptors.iter().map(|t| t.hash))
+            .collect()
+    }
+
+    /// Extension `export` appends to the file name, None if there is nothing to export
+    pub fn export_suffix(&self, style: OutputStyle) -> Option<&'static str> {
+        match self {
+            Self::Tex(_) => Some(".png"),
+            Self::Oft(_) => Some(".otf"),
+            Self::Exe(_) => None,
+            _ if style == OutputStyle::Ndjson => Some(".ndjson"),
+            _ => Some(".json"),
+        }
+    }
+
+    /// Writes the resource next to `output_path` with the extension of the export appended, returns the written files
+    pub fn export(&self, output_path: &Path, style: OutputStyle) -> Result<Vec<PathBuf>> {
+        let Some(suffix) = self.export_suffix(style) else {
+            return Ok(Vec::new())
+        };
+        let output_path = with_suffix(output_path, suffix);
+        // check before creating the file so unsupported resources don't leave empty files behind
+        if let Self::Mesh(_) = self {
+            return Err(self.unsupported_export())
+        }
+        std::fs::create_dir_all(output_path.parent().unwrap())?;
+        let mut writer = BufWriter::new(File::create(&output_path)?);
+        self.write_export(&mut writer, style)?;
+        writer.flush()?;
+        Ok(vec![output_path])
+    }
+
+    /// Writes what `export` would put in the file, json for most resources, png for textures and otf for fonts
+    pub fn write_export<W: Write>(&self, writer: &mut W, style: OutputStyle) -> Result<()> {
+        if style == OutputStyle::Ndjson && self.splits_ndjson() {
+            return self.write_ndjson(writer)
+        }
+        let lossless = style == OutputStyle::Lossless;
+        match self {
+            Self::User(user) => {
+                #[derive(Serialize)]
+                struct Wrapped<'a> {
+                    resource_names: &'a Vec<String>,
+                    children: &'a Vec<UserChild>,
+                    rsz: serde_json::Value,
+                }
+                style.write(writer, &Wrapped {
+                    resource_names: &user.resource_names,
+                    children: &user.children,
+                    rsz: user.rsz.deserialize_to_dersz()?.to_json(lossless),
+                })?
+            }
+            Self::Scn(scn) => style.write(writer, &scn.rsz.deserialize_to_dersz()?.to_json(lossless))?,
+            Self::Rsz(rsz) => style.write(writer, &rsz.deserialize_to_dersz()?.to_json(lossless))?,
+            Self::Pog(pog) => Self::export_pog(pog, writer, style)?,
+            Self::PogList(poglst) => style.write(writer, poglst)?,
+            Self::Msg(msg) => style.write(writer, msg)?,
+            Self::Save(save) => style.write(writer, &to_dersz(save.data.clone())?.to_json(lossless))?,
+            Self::Tex(tex) => Self::export_tex(tex, writer)?,
+            Self::Oft(oft) => writer.write_all(&oft.data)?,
+            Self::Exe(_) | Self::Mesh(_) => return Err(self.unsupported_export()),
+        };
+        Ok(())
+    }
+
+    /// Whether ndjson splits the resource into one line per rsz root. Everything else is written whole on one line,
+    /// so data next to the rsz like the points of a pog is kept
+    fn splits_ndjson(&self) -> bool {
+        matches!(self, Self::User(_) | Self::Save(_))
+    }
+
+    /// One line per rsz root object, with the name of its type.
+    /// User files start with a line holding their resource names and children
+    fn write_ndjson<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let dersz = match self {
+            Self::User(user) => {
+                OutputStyle::Ndjson.write(writer, &serde_json::json!({ "resource_names": user.resource_names, "children": user.children }))?;
+                user.rsz.deserialize_to_dersz()?
+            }
+            Self::Save(save) => to_dersz(save.data.clone())?,
+            _ => return Err(self.unsupported_export()),
+        };
+        for (root, rsz) in dersz.root_values(false) {
+            OutputStyle::Ndjson.write(writer, &serde_json::json!({ "root": root, "rsz": rsz }))?;
+        }
+        Ok(())
+    }
+
+    fn unsupported_export(&self) -> Box<dyn std::error::Error> {
+        ErrorKind::Unsupported(format!("Exporting {} files is not supported", self.format())).into()
+    }
+
+    fn export_pog<W: Write>(pog: &Pog, writer: &mut W, style: OutputStyle) -> Result<()> {
+        #[derive(Serialize)]
+        struct Wrapped<'a> {
+            points: &'a Vec<PogPoint>,
+            graph: &'a Vec<PogNode>,
+            nodes: Vec<serde_json::Value>,// confusing
+        }
+        let nodes = pog.rszs.iter()
+            .map(|rsz| Ok(rsz.deserialize_to_dersz()?.to_json(style == OutputStyle::Lossless)))
+            .collect::<Result<Vec<_>>>()?;
+        style.write(writer, &Wrapped {
+            points: &pog.points,
+            graph: &pog.nodes,
+            nodes,
+        })
+    }
+
+    fn export_tex<W: Write>(tex: &Tex, writer: &mut W) -> Result<()> {
+        let rgba = tex.to_rgba(0, 0)?;
+        PngEncoder::new(writer).write_image(
+            &rgba.data,
+            rgba.width,
+            rgba.height,
+            image::ExtendedColorType::Rgba8,
+        )?;
+        Ok(())
+    }
+
+    /// Turns the resource into something the editor can show
+    pub fn into_editable(self) -> Result<Box<EditableFile>> {
+        match self {
+            Self::User(user) => Ok(Box::new(user.rsz.deserialize_to_dersz()?)),
+            Self::Scn(scn) => Ok(Box::new(scn.rsz.deserialize_to_dersz()?)),
+            Self::Rsz(rsz) => Ok(Box::new(rsz.deserialize_to_dersz()?)),
+            Self::Save(save) => Ok(Box::new(save)),
+            other => Err(ErrorKind::Unsupported(format!("Editing {} files is not supported", other.format())).into()),
+        }
+    }
+}
+
+/// Where the streaming copy of a file is, `natives/STM/x.tex.N` has it in `natives/STM/streaming/x.tex.N`
+pub fn streaming_path(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let start = path.to_lowercase().find("natives/")?;
+    let platform_end = start + "natives/".len() + path[start + "natives/".len()..].find('/')? + 1;
+    if path[platform_end..].to_lowercase().starts_with("streaming/") {
+        return None
+    }
+    Some(format!("{}streaming/{}", &path[..platform_end], &path[platform_end..]))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf();
+    path.set_file_name(path.file_name().unwrap().to_string_lossy().to_string() + suffix);
+    path
+}
+
+/// Recreates a binary file from its json dump, only user files are supported so far
+pub fn build_from_json(json: &str, format: FileFormat) -> Result<Vec<u8>> {
+    match format {
+        FileFormat::User => User::from_json(json)?.to_buf(),
+        _ => Err(ErrorKind::Unsupported(format!("Building {format} files is not supported")).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{game::{item_dump, GameProfile}, reerr::ReError, rsz::rszserde::{DeRsz, DeRszRegistry}, vfs::DirVfs};
+
+    use super::*;
+
+    /// A pog with one node, one point and an rsz block holding a root
+    fn pog() -> Vec<u8> {
+        let mut registry = DeRszRegistry::new();
+        registry.init();
+        let dersz = DeRsz::from_json(&serde_json::json!({
+            "offset": 0,
+            "roots": ["app.Root"],
+            "rsz": [{ "_Extra": { "_Name": "sword", "_Attack": 150 }, "_Values": [] }],
+        }), Rc::new(registry)).unwrap();
+        let rsz = Result::<Rsz>::from(dersz).unwrap().to_buf(0).unwrap();
+
+        let (nodes, points, rsz_start) = (0x58u64, 0x70u64, 0xb0u64);
+        let mut buf = b"POG\0".to_vec();
+        buf.extend(5u32.to_le_bytes());
+        buf.extend(0x1111u64.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend(1u32.to_le_bytes());
+        buf.extend(0x2222u64.to_le_bytes());
+        for offset in [0, nodes, points, rsz_start, rsz_start + rsz.len() as u64, 0, 0] {
+            buf.extend(offset.to_le_bytes());
+        }
+        // the node points at its z and w right after it
+        buf.extend([3u32, 4].iter().flat_map(|v| v.to_le_bytes()));
+        buf.extend((nodes + 16).to_le_bytes());
+        buf.extend(5u32.to_le_bytes());
+        buf.extend(0.5f32.to_le_bytes());
+        buf.extend(1u64.to_le_bytes());
+        buf.extend((points + 16).to_le_bytes());
+        buf.extend([1.0f32; 8].iter().flat_map(|v| v.to_le_bytes()));
+        buf.extend([7i32; 4].iter().flat_map(|v| v.to_le_bytes()));
+        assert_eq!(buf.len() as u64, rsz_start);
+        buf.extend(rsz);
+        buf
+    }
+
+    #[test]
+    fn ndjson_keeps_pog_data() {
+        GameProfile::with_rsz_dump(item_dump()).enter(|| {
+            let resource = Resource::from_reader(Cursor::new(pog()), "a.pog", &LoadOptions::default()).unwrap();
+            let mut ndjson = Vec::new();
+            resource.write_export(&mut ndjson, OutputStyle::Ndjson).unwrap();
+            let mut compact = Vec::new();
+            resource.write_export(&mut compact, OutputStyle::Compact).unwrap();
+
+            let ndjson = String::from_utf8(ndjson).unwrap();
+            assert_eq!(ndjson.lines().count(), 1);
+            let value: serde_json::Value = serde_json::from_str(&ndjson).unwrap();
+            assert_eq!(value, serde_json::from_slice::<serde_json::Value>(&compact).unwrap());
+            assert_eq!(value["graph"][0]["z"], 5);
+            assert_eq!(value["points"][0]["c"], serde_json::json!([7, 7, 7, 7]));
+            assert_eq!(value["nodes"][0]["rsz"][0]["_Extra"]["_Name"], "sword");
+        });
+    }
+
+    /// A mesh with no lods or names whose streaming info lists two ranges, 0..8 and 8..20, the header before the
+    /// offsets is 40 bytes in wilds and 22 in sf6
+    fn mesh(version: u32, header_len: usize) -> Vec<u8> {
+        let (mesh_group, buffers, streaming_info) = (0xb0u64, 0xf0u64, 0x140u64);
+        let mut buf = b"MESH".to_vec();
+        buf.extend(version.to_le_bytes());
+        buf.resize(header_len, 0);
+        for offset in [0, mesh_group, 0, 0, 0, 0, buffers, 0, 0, 0, 0, 0, 0, 0, streaming_info, streaming_info, 0] {
+            buf.extend(offset.to_le_bytes());
+        }
+        buf.resize(buffers as usize, 0);
+        // vertex elements and the vertex buffer are empty
+        buf.extend(streaming_info.to_le_bytes());
+        buf.extend(streaming_info.to_le_bytes());
+        buf.resize(streaming_info as usize, 0);
+        buf.extend(2u32.to_le_bytes());
+        buf.extend(0u32.to_le_bytes());
+        buf.extend((streaming_info + 16).to_le_bytes());
+        buf.extend([0u32, 8, 8, 20].iter().flat_map(|v| v.to_le_bytes()));
+        buf
+    }
+
+    #[test]
+    fn mesh_streaming_merge() {
+        let dir = std::env::temp_dir().join(format!("mhtame_streaming_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("natives/STM/streaming")).unwrap();
+        std::fs::write(dir.join("natives/STM/a.mesh.241111606"), mesh(241111606, 40)).unwrap();
+        std::fs::write(dir.join("natives/STM/b.mesh.241111606"), mesh(241111606, 40)).unwrap();
+        let streaming: Vec<u8> = (0..20).collect();
+        std::fs::write(dir.join("natives/STM/streaming/a.mesh.241111606"), &streaming).unwrap();
+        let vfs = DirVfs::new(dir.clone());
+
+        let Resource::Mesh(mesh) = Resource::load_from_vfs(&vfs, "natives/STM/a.mesh.241111606", &LoadOptions::default()).unwrap() else {
+            panic!("not a mesh")
+        };
+        assert_eq!(mesh.streaming_buffers, [&streaming[..8], &streaming[8..]]);
+        // without a streaming copy the mesh is read as is
+        let Resource::Mesh(mesh) = Resource::load_from_vfs(&vfs, "natives/STM/b.mesh.241111606", &LoadOptions::default()).unwrap() else {
+            panic!("not a mesh")
+        };
+        assert!(mesh.has_streaming() && mesh.streaming_buffers.is_empty());
+
+        std::fs::write(dir.join("natives/STM/streaming/a.mesh.241111606"), &streaming[..12]).unwrap();
+        let e = Resource::load_from_vfs(&vfs, "natives/STM/a.mesh.241111606", &LoadOptions::default()).err().unwrap();
+        assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("data_too_short"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mesh_layout_follows_game() {
+        let sf6 = GameProfile::new("sf6", "", "").unwrap().leak();
+        sf6.enter(|| {
+            let Resource::Mesh(sf6_mesh) = Resource::from_reader(Cursor::new(mesh(230110883, 22)), "a.mesh.230110883", &LoadOptions::default()).unwrap() else {
+                panic!("not a mesh")
+            };
+            assert_eq!(sf6_mesh.streaming_ranges.len(), 2);
+            let e = Resource::from_reader(Cursor::new(mesh(241111606, 40)), "a.mesh.241111606", &LoadOptions::default()).err().unwrap();
+            assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("unsupported_file_version"));
+        });
+        assert!(GameProfile::new("mhworld", "", "").is_err());
+    }
+}
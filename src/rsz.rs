@@ -1,18 +1,23 @@
 pub mod rszserde;
 pub mod object;
 pub mod dump;
+pub mod query;
 use byteorder::LittleEndian;
 use byteorder::WriteBytesExt;
 use dump::RszDump;
 use rszserde::DeRsz;
 use rszserde::DeRszType;
+use rszserde::ExternObject;
+use rszserde::FieldOffset;
+use rszserde::RawInstance;
 use rszserde::RszDeserializerCtx;
 use rszserde::RszSerializerCtx;
 
 use crate::file_ext::*;
+use crate::format::FileFormat;
+use crate::version::{Layout, VersionEntry};
 
 use std::collections::HashMap;
-use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::io::Cursor;
 use std::io::Write;
@@ -37,40 +42,103 @@ pub struct TypeDescriptor {
 pub struct Rsz {
     version: u32,
     offset: usize,
+    data_offset: u64,
     pub roots: Vec<u32>,
     pub extern_slots: HashMap<u32, Extern>,
     pub type_descriptors: Vec<TypeDescriptor>,
     pub data: Vec<u8>,
+    /// Zero bytes added after the data, they are not in the file
+    padding: usize,
+    /// Deserialize instances of types missing from the dump as raw bytes
+    pub lenient: bool,
 }
 
-impl Rsz {
-    pub fn new<F: Read + Seek>(file: &mut F, base: u64, cap: u64) -> Result<Rsz> {
+/// The header of an rsz block, the fields it has depend on the version
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RszHeader {
+    pub version: u32,
+    pub root_count: u32,
+    pub type_descriptor_count: u32,
+    pub extern_count: u32,
+    pub type_descriptor_offset: u64,
+    pub data_offset: u64,
+    pub extern_offset: u64,
+}
+
+impl RszHeader {
+    /// Version new rsz blocks are written with
+    pub const VERSION: u32 = 0x10;
+
+    /// If the header of the version has the extern slot fields
+    pub fn has_externs(version: u32) -> Result<bool> {
+        let Layout::Rsz { externs } = VersionEntry::layout(FileFormat::Rsz, version)? else {
+            unreachable!("rsz versions have an rsz layout")
+        };
+        Ok(externs)
+    }
+
+    pub fn size(version: u32) -> Result<usize> {
+        Ok(if Self::has_externs(version)? { 0x30 } else { 0x20 })
+    }
+
+    pub fn read<F: Read + Seek>(file: &mut F, base: u64) -> Result<Self> {
         file.seek(SeekFrom::Start(base))?;
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?;
-        if ext != "RSZ\0" {
-            return Err(Box::new(FileParseError::MagicError { 
-                real_magic: String::from("RSZ"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"RSZ\0" {
+            return Err(Box::new(ReError::magic(b"RSZ\0", &magic).at(base)))
         }
 
         let version = file.read_u32()?;
-        if version != 0x10 {
-            return Err(format!("Unexpected RSZ version {}", version).into());
-        }
-
+        let externs = Self::has_externs(version).map_err(|e| ReError::from_boxed(e).at(base + 4))?;
         let root_count = file.read_u32()?;
         let type_descriptor_count = file.read_u32()?;
-        let extern_count = file.read_u32()?;
-        let padding = file.read_u32()?;
-        if padding != 0 {
-            return Err(format!("Unexpected non-zero padding in RSZ: {}", padding).into());
+        let mut extern_count = 0;
+        if externs {
+            extern_count = file.read_u32()?;
+            let padding = file.read_u32()?;
+            if padding != 0 {
+                return Err(Box::new(ReError::new(ErrorKind::NonZeroPadding { what: "RSZ header", value: padding as u64 }).at(base + 0x14)));
+            }
         }
-
         let type_descriptor_offset = file.read_u64()?;
         let data_offset = file.read_u64()?;
-        let extern_offset = file.read_u64()?;
+        let extern_offset = if externs { file.read_u64()? } else { 0 };
+        Ok(Self { version, root_count, type_descriptor_count, extern_count, type_descriptor_offset, data_offset, extern_offset })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let externs = Self::has_externs(self.version)?;
+        if !externs && self.extern_count != 0 {
+            return Err(ErrorKind::InvalidValue(format!("RSZ version {:#x} has no extern slots", self.version)).into())
+        }
+        writer.write_all(b"RSZ\0")?;
+        writer.write_u32::<LittleEndian>(self.version)?;
+        writer.write_u32::<LittleEndian>(self.root_count)?;
+        writer.write_u32::<LittleEndian>(self.type_descriptor_count)?;
+        if externs {
+            writer.write_u32::<LittleEndian>(self.extern_count)?;
+            writer.write_all(&[0; 4])?;
+        }
+        writer.write_u64::<LittleEndian>(self.type_descriptor_offset)?;
+        writer.write_u64::<LittleEndian>(self.data_offset)?;
+        if externs {
+            writer.write_u64::<LittleEndian>(self.extern_offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rsz {
+    pub fn new<F: Read + Seek>(file: &mut F, base: u64, cap: u64) -> Result<Rsz> {
+        let RszHeader {
+            version,
+            root_count,
+            type_descriptor_count,
+            extern_count,
+            type_descriptor_offset,
+            data_offset,
+            extern_offset,
+        } = RszHeader::read(file, base)?;
 
         let roots = (0..root_count)
             .map(|_| file.read_u32())
@@ -87,7 +155,7 @@ impl Rsz {
             .collect::<Result<Vec<_>>>()?;
 
         if type_descriptors.first() != Some(&TypeDescriptor { hash: 0, crc: 0 }) {
-            return Err(format!("The first type descriptor should be 0").into())
+            return Err(Box::new(ReError::new(ErrorKind::InvalidValue("The first type descriptor should be 0".to_string())).at(base + type_descriptor_offset)))
         }
 
         //file.seek_assert_align_up(base + extern_offset, 16)?;
@@ -109,14 +177,12 @@ impl Rsz {
                 file.seek_noop(base + offset)?;
                 let path = file.read_u16str()?;
                 if !path.ends_with(".user") {
-                    return Err(format!("Non-USER slot string").into());
+                    return Err(ReError::new(ErrorKind::InvalidSlot(format!("Non-USER slot string {path}"))).at(base + offset).into());
                 }
-                if hash != type_descriptors
-                        .get(usize::try_from(slot)?)
-                        .expect("slot out of bound")
-                        .hash
-                {
-                    return Err(format!("slot hash mismatch").into())
+                let descriptor = type_descriptors.get(slot as usize)
+                    .ok_or(ReError::new(ErrorKind::InvalidSlot(format!("slot {slot} out of bounds"))).at(base + offset))?;
+                if hash != descriptor.hash {
+                    return Err(ReError::new(ErrorKind::InvalidSlot(format!("slot {slot} hash mismatch {hash:08x} != {:08x}", descriptor.hash))).at(base + offset).into())
                 }
                 Ok((slot, Extern { hash, path }))
             })
@@ -127,6 +193,7 @@ impl Rsz {
         file.seek(SeekFrom::Start(base + data_offset))?;
         file.seek_align_up(16)?;
         let mut data: Vec<u8> = vec![];
+        let padding;
         if cap != 0 {
             let len = (cap) as usize - (base + data_offset) as usize;
             let current_pos = file.seek(SeekFrom::Current(0))?;
@@ -134,7 +201,7 @@ impl Rsz {
             file.seek(SeekFrom::Start(base + data_offset))?;
             let remaining = (total_size - current_pos) as usize;
             if len as u64 > remaining as u64 {
-                println!("[WARNING] adding extra bytes to end of RSZ");
+                log::warn!("adding extra bytes to end of RSZ");
                 data = vec![0u8; remaining];
             }
             else {
@@ -143,28 +210,57 @@ impl Rsz {
             file.read_exact(&mut data)?;
 
             // add some extra bytes in case
-            if len as u64 > remaining as u64 {
-                data.extend(vec![0; len - remaining]);
-            }
+            padding = len.saturating_sub(remaining);
+            data.extend(vec![0; padding]);
         } else {
             file.read_to_end(&mut data)?;
-            data.extend(vec![0; 128]);
+            padding = 128;
+            data.extend(vec![0; padding]);
         };
         Ok(Rsz {
             version,
             offset: base as usize,
+            data_offset,
             roots,
             extern_slots,
             type_descriptors,
             data,
+            padding,
+            lenient: false,
         })
     }
 
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn padding(&self) -> usize {
+        self.padding
+    }
+
+    /// Start of the rsz block in the file it was read from
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Start of the instance data, relative to the rsz block
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// Where each field of each instance starts in the data
+    pub fn field_offsets(&self) -> Result<Vec<FieldOffset>> {
+        let mut ctx = RszDeserializerCtx::from(self);
+        DeRsz::from_bytes(&mut ctx)?;
+        Ok(ctx.field_offsets)
+    }
+
     // Deserializes the Rsz data into a DeRsz, consuming the Rsz
     pub fn deserialize_to_dersz(&self) -> Result<DeRsz>
     {
         let mut ctx = RszDeserializerCtx::from(self);
-        let x = DeRsz::from_bytes(&mut ctx)?;
+        let mut x = DeRsz::from_bytes(&mut ctx)?;
+        x.version = self.version;
 
         /*let mut leftover = vec![];
         ctx.boxed.read_to_end(&mut leftover)?;
@@ -178,23 +274,28 @@ impl Rsz {
 
     pub fn to_buf(&self, _start_addr: usize) -> Result<Vec<u8>> {
         let mut buf = vec![];
-        buf.write_all(b"RSZ\0")?;
-        buf.write_u32::<LittleEndian>(self.version)?;
-        buf.write_u32::<LittleEndian>(self.roots.len() as u32)?;
-        buf.write_u32::<LittleEndian>(self.type_descriptors.len() as u32)?;
-        buf.write_u32::<LittleEndian>(self.extern_slots.len() as u32)?;
-        buf.write_all(&[0; 4])?;
-        let type_descriptor_offset = buf.len() + self.roots.len() * size_of::<u32>() + 3 * size_of::<u64>();
+        let type_descriptor_offset = RszHeader::size(self.version)? + self.roots.len() * size_of::<u32>();
 
         let mut extern_offset = type_descriptor_offset + self.type_descriptors.len() * size_of::<u64>();
         if extern_offset % 16 != 0 { extern_offset += 16 - extern_offset % 16; }
-        let mut data_offset = extern_offset + self.extern_slots.len() * size_of::<u32>();
+        // slot, hash and path offset per extern, then the paths
+        let mut slots: Vec<(&u32, &Extern)> = self.extern_slots.iter().collect();
+        slots.sort_by_key(|(slot, _)| **slot);
+        let paths: Vec<Vec<u8>> = slots.iter().map(|(_, ext)| utf16z(&ext.path)).collect();
+        let strings_offset = extern_offset + slots.len() * (size_of::<u32>() * 2 + size_of::<u64>());
+        let mut data_offset = strings_offset + paths.iter().map(Vec::len).sum::<usize>();
         if data_offset % 16 != 0 { data_offset += 16 - data_offset % 16; }
 
-        println!("{:x}, {:x}, {:x}, {:x}", type_descriptor_offset, extern_offset, data_offset, _start_addr);
-        buf.write_u64::<LittleEndian>(type_descriptor_offset as u64)?;
-        buf.write_u64::<LittleEndian>(data_offset as u64)?;
-        buf.write_u64::<LittleEndian>(extern_offset as u64)?;
+        log::debug!("{:x}, {:x}, {:x}, {:x}", type_descriptor_offset, extern_offset, data_offset, _start_addr);
+        RszHeader {
+            version: self.version,
+            root_count: self.roots.len() as u32,
+            type_descriptor_count: self.type_descriptors.len() as u32,
+            extern_count: self.extern_slots.len() as u32,
+            type_descriptor_offset: type_descriptor_offset as u64,
+            data_offset: data_offset as u64,
+            extern_offset: extern_offset as u64,
+        }.write(&mut buf)?;
         for root in &self.roots {
             buf.write_u32::<LittleEndian>(*root)?;
         }
@@ -206,18 +307,20 @@ impl Rsz {
             buf.write_u32::<LittleEndian>(descriptor.hash)?;
             buf.write_u32::<LittleEndian>(descriptor.crc)?;
         }
-        println!("{}", buf.len());
         if buf.len() != extern_offset {
             buf.extend(vec![0; extern_offset as usize - buf.len()]);
         }
-        println!("{}", size_of::<u32>());
-        // Figure this out
-        //for extern in &self.extern_slots {
-        //    buf.write_u32::<LittleEndian>(descriptor.hash)?;
-        //    buf.write_u32::<LittleEndian>(descriptor.crc)?;
-        //}
+        let mut path_offset = strings_offset;
+        for ((slot, ext), path) in slots.iter().zip(&paths) {
+            buf.write_u32::<LittleEndian>(**slot)?;
+            buf.write_u32::<LittleEndian>(ext.hash)?;
+            buf.write_u64::<LittleEndian>(path_offset as u64)?;
+            path_offset += path.len();
+        }
+        for path in &paths {
+            buf.write_all(path)?;
+        }
         if buf.len() != data_offset {
-            println!("{}, {}", buf.len(), data_offset);
             buf.extend(vec![0; data_offset as usize - buf.len()]);
         }
         buf.extend(&self.data);
@@ -225,13 +328,22 @@ impl Rsz {
     }
 }
 
+/// Null terminated utf16, how paths are stored in rsz and user files
+pub fn utf16z(s: &str) -> Vec<u8> {
+    s.encode_utf16().chain([0]).flat_map(|c| c.to_le_bytes()).collect()
+}
+
 impl From<DeRsz> for Result<Rsz> {
     fn from(dersz: DeRsz) -> Self {
-        let type_descriptors = dersz.structs.iter().map(|(hash, _)| {
-            let crc = RszDump::get_struct(*hash).unwrap().crc;
+        let type_descriptors = dersz.structs.iter().map(|(hash, values)| {
+            let crc = match values.first().and_then(|v| v.as_any().downcast_ref::<RawInstance>()) {
+                Some(raw) => raw.crc,
+                None => RszDump::get_struct(*hash).unwrap().crc,
+            };
             TypeDescriptor{hash: *hash, crc}
         }).collect();
         let mut buffer = Vec::new();
+        let mut extern_slots = HashMap::new();
         {
             let mut cursor = Cursor::new(&mut buffer);
             let mut ctx = RszSerializerCtx {
@@ -239,15 +351,226 @@ impl From<DeRsz> for Result<Rsz> {
                 base_addr: 0
             };
 
-            for (hash, val) in dersz.structs {
+            for (i, (hash, val)) in dersz.structs.into_iter().enumerate() {
                 if hash == 0 { continue; }
                 let s = &val[0]; // dumb stupid
+                if let Some(extern_obj) = s.as_any().downcast_ref::<ExternObject>() {
+                    extern_slots.insert(i as u32, Extern { hash, path: extern_obj.path().to_string() });
+                    continue;
+                }
                 ctx.base_addr = dersz.offset;// + rszserde::get_writer_length(ctx.data)? as usize;
                 s.to_bytes(&mut ctx)?;
             }
         }
-        Ok(Rsz{ version: 0x10, offset: dersz.offset, roots: dersz.roots, extern_slots: HashMap::new(), type_descriptors, data: buffer })
+        Ok(Rsz{ version: dersz.version, offset: dersz.offset, data_offset: 0, roots: dersz.roots, extern_slots, type_descriptors, data: buffer, padding: 0, lenient: false })
+
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::game::{dump_field, item_dump, GameProfile};
+    use rszserde::DeRszRegistry;
+
+    use super::*;
+
+    /// An rsz block with one root of type 0xabc holding a u32
+    fn block(version: u32) -> Vec<u8> {
+        let header = RszHeader {
+            version,
+            root_count: 1,
+            type_descriptor_count: 2,
+            type_descriptor_offset: RszHeader::size(version).unwrap() as u64 + 4,
+            ..Default::default()
+        };
+        let data_offset = (header.type_descriptor_offset + 16).div_ceil(16) * 16;
+        let mut buf = vec![];
+        RszHeader { data_offset, extern_offset: data_offset, ..header }.write(&mut buf).unwrap();
+        buf.extend(1u32.to_le_bytes());
+        buf.extend([0; 8]);
+        buf.extend(0xabcu32.to_le_bytes());
+        buf.extend(7u32.to_le_bytes());
+        buf.resize(data_offset as usize, 0);
+        buf.extend(5u32.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn header_round_trip() {
+        for (version, size) in [(0x10, 0x30), (3, 0x20)] {
+            let header = RszHeader {
+                version,
+                root_count: 2,
+                type_descriptor_count: 3,
+                extern_count: if version == 3 { 0 } else { 1 },
+                type_descriptor_offset: 0x38,
+                data_offset: 0x60,
+                extern_offset: if version == 3 { 0 } else { 0x50 },
+            };
+            let mut buf = vec![];
+            header.write(&mut buf).unwrap();
+            assert_eq!(buf.len(), size);
+            assert_eq!(RszHeader::read(&mut Cursor::new(&buf), 0).unwrap(), header);
+        }
+    }
 
+    #[test]
+    fn old_header_has_no_externs() {
+        let header = RszHeader { version: 3, extern_count: 1, ..Default::default() };
+        assert!(header.write(&mut vec![]).is_err());
     }
 
+    #[test]
+    fn unknown_version() {
+        let mut buf = b"RSZ\0".to_vec();
+        buf.extend(7u32.to_le_bytes());
+        buf.resize(0x30, 0);
+        assert!(RszHeader::read(&mut Cursor::new(&buf), 0).is_err());
+    }
+
+    #[test]
+    fn read_at_base() {
+        let mut buf = vec![0; 0x10];
+        buf.extend(block(3));
+        let header = RszHeader::read(&mut Cursor::new(&buf), 0x10).unwrap();
+        assert_eq!((header.version, header.root_count, header.type_descriptor_offset), (3, 1, 0x24));
+    }
+
+    #[test]
+    fn block_round_trip() {
+        for version in [0x10, 3] {
+            let buf = block(version);
+            let rsz = Rsz::new(&mut Cursor::new(&buf), 0, buf.len() as u64).unwrap();
+            assert_eq!(rsz.version(), version);
+            assert_eq!(rsz.roots, vec![1]);
+            assert_eq!(rsz.type_descriptors[1], TypeDescriptor { hash: 0xabc, crc: 7 });
+            assert_eq!(rsz.data, 5u32.to_le_bytes());
+            assert_eq!(rsz.to_buf(0).unwrap(), buf);
+        }
+    }
+
+    #[test]
+    fn externs_round_trip() {
+        let mut rsz = Rsz::new(&mut Cursor::new(block(0x10)), 0, 0).unwrap();
+        rsz.data.truncate(4);
+        rsz.extern_slots.insert(1, Extern { hash: 0xabc, path: "natives/STM/a.user".to_string() });
+        let buf = rsz.to_buf(0).unwrap();
+        let again = Rsz::new(&mut Cursor::new(&buf), 0, buf.len() as u64).unwrap();
+        assert_eq!(again.extern_slots[&1].path, "natives/STM/a.user");
+        assert_eq!(again.data, 5u32.to_le_bytes());
+
+        rsz.version = 3;
+        assert!(rsz.to_buf(0).is_err());
+    }
+
+    /// The test items and app.Extra, which the lenient profile doesn't know. Both type `_Extra` as an item since
+    /// objects are read with the type of their field
+    fn profile(with_extra: bool) -> &'static GameProfile {
+        let mut rsz = item_dump();
+        if with_extra {
+            let fields = [dump_field("_Child", "Object", "app.Item", false), dump_field("_Value", "S32", "System.Int32", false)];
+            rsz["300"] = serde_json::json!({ "name": "app.Extra", "crc": "3", "fields": fields });
+        }
+        GameProfile::with_rsz_dump(rsz)
+    }
+
+    /// Instance 1 is the item only app.Extra points at, 2 the app.Extra
+    fn extra_block() -> Rsz {
+        let dersz = DeRsz::from_json(&serde_json::json!({
+            "offset": 0,
+            "roots": ["app.Root"],
+            "rsz": [{
+                "_Extra": { "raw_type": "00000300", "raw_crc": "00000003", "raw_data": "0100000007000000", "raw_objects": [
+                    { "type": "app.Item", "value": { "_Name": "sword", "_Attack": 150 } },
+                ] },
+                "_Values": [{ "_Name": "axe", "_Attack": 250 }],
+            }],
+        }), Rc::new(registry())).unwrap();
+        Result::<Rsz>::from(dersz).unwrap()
+    }
+
+    fn registry() -> DeRszRegistry {
+        let mut registry = DeRszRegistry::new();
+        registry.init();
+        registry
+    }
+
+    #[test]
+    fn lenient_reads_unknown_instance() {
+        let strict = profile(true);
+        let lenient = profile(false);
+        let rsz = lenient.enter(extra_block);
+        assert_eq!(rsz.type_descriptors.iter().map(|td| td.hash).collect::<Vec<_>>(), [0, 0x100, 0x300, 0x100, 0x200]);
+        // the profile that knows app.Extra reads the same bytes field by field
+        assert_eq!(strict.enter(|| rsz.deserialize_to_dersz()).unwrap().structs[2].1.len(), 2);
+        lenient.enter(|| {
+            assert!(rsz.deserialize_to_dersz().is_err());
+            let rsz = Rsz { lenient: true, ..rsz };
+            let dersz = rsz.deserialize_to_dersz().unwrap();
+            let raw = dersz.structs[2].1[0].as_any().downcast_ref::<RawInstance>().unwrap();
+            assert_eq!((raw.hash, raw.crc, raw.data.as_slice()), (0x300, 3, [1, 0, 0, 0, 7, 0, 0, 0].as_slice()));
+            // the item after it is read from where the raw data ends
+            assert_eq!(dersz.get_field(3, "_Attack").unwrap().as_any().downcast_ref::<i32>(), Some(&250));
+        });
+    }
+
+    #[test]
+    fn raw_instance_json_round_trip() {
+        let lenient = profile(false);
+        lenient.enter(|| {
+            let rsz = Rsz { lenient: true, ..extra_block() };
+            let json = rsz.deserialize_to_dersz().unwrap().to_json(false);
+            let extra = &json["rsz"][0]["_Extra"];
+            assert_eq!(extra["raw_data"], "0100000007000000");
+            // the item only the raw data points at is written with it
+            assert_eq!(extra["raw_objects"][0]["value"]["_Name"], "sword");
+            let rebuilt = Result::<Rsz>::from(DeRsz::from_json(&json, Rc::new(registry())).unwrap()).unwrap();
+            assert_eq!((&rebuilt.type_descriptors, &rebuilt.data), (&rsz.type_descriptors, &rsz.data));
+        });
+    }
+
+    #[test]
+    fn raw_root_keeps_its_objects() {
+        let strict = profile(true);
+        let lenient = profile(false);
+        let rsz = strict.enter(|| {
+            let dersz = DeRsz::from_json(&serde_json::json!({
+                "offset": 0,
+                "roots": ["app.Extra"],
+                "rsz": [{ "_Child": { "_Name": "sword", "_Attack": 150 }, "_Value": 7 }],
+            }), Rc::new(registry())).unwrap();
+            Result::<Rsz>::from(dersz).unwrap()
+        });
+        lenient.enter(|| {
+            let rsz = Rsz { lenient: true, ..rsz };
+            let json = rsz.deserialize_to_dersz().unwrap().to_json(false);
+            assert_eq!(json["roots"][0], "00000300");
+            assert_eq!(json["rsz"][0]["raw_objects"][0]["type"], "app.Item");
+            let rebuilt = Result::<Rsz>::from(DeRsz::from_json(&json, Rc::new(registry())).unwrap()).unwrap();
+            assert_eq!((&rebuilt.roots, &rebuilt.data), (&rsz.roots, &rsz.data));
+        });
+    }
+
+    #[test]
+    fn unknown_instance_without_fit() {
+        let lenient = profile(false);
+        let mut rsz = Rsz { lenient: true, ..lenient.enter(extra_block) };
+        // the items after the raw data can't read up to an end that is off by two bytes
+        rsz.data.extend([0; 2]);
+        let e = lenient.enter(|| rsz.deserialize_to_dersz()).unwrap_err();
+        assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("unknown_rsz_extent"));
+    }
+
+    #[test]
+    fn unknown_instance_followed_by_unknown() {
+        let lenient = profile(false);
+        let mut rsz = Rsz { lenient: true, ..lenient.enter(extra_block) };
+        // instance 3 is an unknown type too, so where instance 2 ends can't be found
+        rsz.type_descriptors[3].hash = 0x400;
+        let e = lenient.enter(|| rsz.deserialize_to_dersz()).unwrap_err();
+        assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("unknown_rsz_extent"));
+    }
 }
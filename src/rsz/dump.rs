@@ -3,7 +3,7 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::reerr::{Result, RszError::*};
+use crate::{game::{EnumMap, GameProfile}, reerr::{ErrorKind, Result}};
 
 pub static RSZ_FILE: OnceLock<String> = OnceLock::new();
 pub static ENUM_FILE: OnceLock<String> = OnceLock::new();
@@ -90,33 +90,17 @@ impl RszDump {
     pub fn get_struct<'a>(hash: u32) -> Result<&'a RszStruct<RszField>> {
         match RszDump::rsz_map().get(&hash) {
             Some(struct_desc) => Ok(struct_desc),
-            None => Err(InvalidRszTypeHash(hash).into())
+            None => Err(ErrorKind::UnknownRszHash(hash).into())
         }
     }
 
+    /// Types of the active game profile
     pub fn rsz_map() -> &'static RszMap<RszMapType> {
-        static HASHMAP: OnceLock<RszMap<RszMapType>> = OnceLock::new();
-        HASHMAP.get_or_init(|| {
-            RSZ_FILE.get_or_init(|| {
-                "rszmhwilds.json".to_string()
-            });
-            let file = std::fs::read_to_string(RSZ_FILE.get().unwrap()).unwrap();
-            let m: RszMapType = serde_json::from_str(&file).unwrap();
-            RszMap(m)
-        })
+        GameProfile::active().rsz_map()
     }
 
     pub fn name_map() -> &'static RszMap<RszNameMapType> {
-        static HASHMAP: OnceLock<RszMap<RszNameMapType>> = OnceLock::new();
-        HASHMAP.get_or_init(|| {
-            let temp = &Self::rsz_map().0;
-            let mut m = HashMap::new();
-            for (_key, rsz_struct) in temp {
-                let hash = u32::from_str_radix(&_key, 16).unwrap();
-                m.insert(rsz_struct.name.clone(), hash);
-            }
-            RszMap(m)
-        })
+        GameProfile::active().name_map()
     }
 }
 
@@ -159,18 +143,9 @@ pub fn get_enum_val(name: &str, enum_str_name: &str) -> Option<i128> {
 }
 
 
-type EnumMap = HashMap<String, HashMap<String, String>>;
-
+/// Enums of the active game profile
 pub fn enum_map() -> &'static EnumMap {
-    static HASHMAP: OnceLock<EnumMap> = OnceLock::new();
-    HASHMAP.get_or_init(|| {
-        ENUM_FILE.get_or_init(|| {
-            "enums.json".to_string()
-        });
-        let json_data = std::fs::read_to_string(ENUM_FILE.get().unwrap()).unwrap();
-        let hashmap: EnumMap = serde_json::from_str(&json_data).unwrap();
-        hashmap
-    })
+    GameProfile::active().enum_map()
 }
 
 pub fn get_enum_name(name: &str, value: &str) -> Option<String> {
@@ -0,0 +1,374 @@
+/*
+ * Path based access to the values of a DeRsz, like `root[0]._Values[12]._Name`
+ */
+
+use std::collections::HashSet;
+
+use crate::reerr::{ErrorKind, Result};
+use super::dump::RszDump;
+use super::rszserde::{DeRsz, DeRszInstance, ExternObject, Object, StringU16, Struct};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+/// Splits a path like `root[0]._Values[12]._Name` into fields and indices
+pub fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (name, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() && (segments.is_empty() || rest.is_empty()) {
+            return Err(invalid_path(path, "empty field name"))
+        }
+        if !name.is_empty() {
+            segments.push(Segment::Field(name.to_string()));
+        }
+        while !rest.is_empty() {
+            let end = rest.find(']').ok_or_else(|| invalid_path(path, "missing ]"))?;
+            let idx = rest[1..end].parse().map_err(|_| invalid_path(path, &format!("bad index {}", &rest[1..end])))?;
+            segments.push(Segment::Index(idx));
+            rest = &rest[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return Err(invalid_path(path, "expected . or [ after ]"))
+            }
+        }
+    }
+    Ok(segments)
+}
+
+fn invalid_path(path: &str, reason: &str) -> Box<dyn std::error::Error> {
+    ErrorKind::InvalidPath { path: path.to_string(), reason: reason.to_string() }.into()
+}
+
+/// Numbers, bools and enums as f64
+pub fn as_f64(value: &dyn DeRszInstance) -> Option<f64> {
+    let any = value.as_any();
+    macro_rules! try_num {
+        ($($t:ty),*) => {
+            $(if let Some(v) = any.downcast_ref::<$t>() { return Some(*v as f64) })*
+        };
+    }
+    try_num!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+    any.downcast_ref::<bool>().map(|v| *v as u8 as f64)
+}
+
+/// Strings, resources and runtime types
+pub fn as_string(value: &dyn DeRszInstance) -> Option<String> {
+    let any = value.as_any();
+    if let Some(s) = any.downcast_ref::<StringU16>() {
+        return Some(String::from_utf16_lossy(&s.0).trim_end_matches('\0').to_string())
+    }
+    any.downcast_ref::<String>().cloned()
+}
+
+/// Objects that point at an instance of the same rsz, not an extern one
+fn as_object(value: &dyn DeRszInstance) -> Option<&Object> {
+    value.as_any().downcast_ref::<Object>()
+}
+
+impl DeRsz {
+    /// Value at a path starting with `root[n]` or `object[n]`, objects along the way are followed
+    pub fn get(&self, path: &str) -> Result<&dyn DeRszInstance> {
+        let (object, segments) = self.start(path)?;
+        self.get_in(object, path, &segments)
+    }
+
+    /// Value at a path relative to an instance, like `_Values[12]._Name`
+    pub fn get_field(&self, object: u32, path: &str) -> Result<&dyn DeRszInstance> {
+        self.get_in(object, path, &parse_path(path)?)
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Result<&mut Box<dyn DeRszInstance>> {
+        let (object, segments) = self.start(path)?;
+        self.get_mut_in(object, path, &segments)
+    }
+
+    pub fn get_field_mut(&mut self, object: u32, path: &str) -> Result<&mut Box<dyn DeRszInstance>> {
+        self.get_mut_in(object, path, &parse_path(path)?)
+    }
+
+    pub fn get_as<T: 'static>(&self, path: &str) -> Result<&T> {
+        self.get(path)?.as_any().downcast_ref::<T>()
+            .ok_or_else(|| invalid_path(path, &format!("value is not a {}", std::any::type_name::<T>())))
+    }
+
+    pub fn get_f64(&self, path: &str) -> Result<f64> {
+        as_f64(self.get(path)?).ok_or_else(|| invalid_path(path, "value is not a number"))
+    }
+
+    pub fn get_string(&self, path: &str) -> Result<String> {
+        as_string(self.get(path)?).ok_or_else(|| invalid_path(path, "value is not a string"))
+    }
+
+    /// Replaces the value at a path, the new value has to be of the same type as the old one
+    pub fn set<T: DeRszInstance>(&mut self, path: &str, value: T) -> Result<()> {
+        let objects = self.structs.len();
+        let slot = self.get_mut(path)?;
+        if !slot.as_any().is::<T>() {
+            return Err(invalid_path(path, &format!("value is not a {}", std::any::type_name::<T>())))
+        }
+        if let Some(object) = value.as_any().downcast_ref::<Object>()
+            && object.idx as usize >= objects {
+            return Err(ErrorKind::InvalidObjectIndex { idx: object.idx, hash: object.hash }.into())
+        }
+        *slot = Box::new(value);
+        Ok(())
+    }
+
+    /// Instance a path leads to, `root[0]` or `root[0]._Param` if `_Param` is an object
+    pub fn object_at(&self, path: &str) -> Result<u32> {
+        let (object, segments) = self.start(path)?;
+        let (object, steps) = self.resolve(object, path, &segments)?;
+        if steps.is_empty() {
+            return Ok(object)
+        }
+        let value = self.walk(object, path, &steps)?;
+        as_object(value).map(|object| object.idx).ok_or_else(|| invalid_path(path, "value is not an object"))
+    }
+
+    pub fn type_name(&self, object: u32) -> Option<&'static str> {
+        let (hash, _) = self.structs.get(object as usize)?;
+        RszDump::get_struct(*hash).ok().map(|desc| desc.name.as_str())
+    }
+
+    /// Indices of the instances of a type, extern objects excluded
+    pub fn objects_of_type<'a>(&'a self, name: &str) -> impl Iterator<Item = u32> + 'a {
+        let hash = RszDump::name_map().get(&name.to_string()).copied();
+        self.structs.iter().enumerate()
+            .filter(move |(i, (h, _))| Some(*h) == hash && !self.extern_idxs.contains(&(*i as u32)))
+            .map(|(i, _)| i as u32)
+    }
+
+    /// Path from a root to an instance, like `root[0]._Values[12]`
+    pub fn path_of(&self, object: u32) -> Option<String> {
+        let mut seen = HashSet::new();
+        self.roots.iter().enumerate().find_map(|(i, root)| {
+            self.find_path(*root, object, format!("root[{i}]"), &mut seen)
+        })
+    }
+
+    fn find_path(&self, from: u32, to: u32, path: String, seen: &mut HashSet<u32>) -> Option<String> {
+        if from == to {
+            return Some(path)
+        }
+        if !seen.insert(from) {
+            return None
+        }
+        let desc = RszDump::get_struct(self.structs.get(from as usize)?.0).ok()?;
+        let values = self.fields(from).ok()?;
+        desc.fields.iter().zip(values).find_map(|(field, value)| {
+            self.find_path_in(value.as_ref(), to, format!("{path}.{}", field.name), seen)
+        })
+    }
+
+    fn find_path_in(&self, value: &dyn DeRszInstance, to: u32, path: String, seen: &mut HashSet<u32>) -> Option<String> {
+        if let Some(object) = as_object(value) {
+            return self.find_path(object.idx, to, path, seen)
+        }
+        if let Some(values) = value.as_any().downcast_ref::<Vec<Box<dyn DeRszInstance>>>() {
+            return values.iter().enumerate().find_map(|(i, value)| {
+                self.find_path_in(value.as_ref(), to, format!("{path}[{i}]"), seen)
+            })
+        }
+        let s = value.as_any().downcast_ref::<Struct>()?;
+        let desc = RszDump::get_struct(s.hash).ok()?;
+        desc.fields.iter().zip(&s.values).find_map(|(field, value)| {
+            self.find_path_in(value.as_ref(), to, format!("{path}.{}", field.name), seen)
+        })
+    }
+
+    /// Instance the path starts at and the rest of the path
+    fn start(&self, path: &str) -> Result<(u32, Vec<Segment>)> {
+        let segments = parse_path(path)?;
+        let object = match segments.get(..2) {
+            Some([Segment::Field(name), Segment::Index(i)]) if name == "root" => *self.roots.get(*i)
+                .ok_or_else(|| invalid_path(path, &format!("there are {} roots", self.roots.len())))?,
+            Some([Segment::Field(name), Segment::Index(i)]) if name == "object" => {
+                if *i >= self.structs.len() {
+                    return Err(invalid_path(path, &format!("there are {} objects", self.structs.len())))
+                }
+                *i as u32
+            }
+            _ => return Err(invalid_path(path, "should start with root[n] or object[n]")),
+        };
+        Ok((object, segments[2..].to_vec()))
+    }
+
+    /// Values of the fields of an instance, in the order of the dump
+    fn fields(&self, object: u32) -> Result<&[Box<dyn DeRszInstance>]> {
+        let (hash, values) = self.structs.get(object as usize)
+            .ok_or(ErrorKind::InvalidObjectIndex { idx: object, hash: 0 })?;
+        // instances read from json keep their fields in a Struct
+        if let [value] = values.as_slice()
+            && let Some(s) = value.as_any().downcast_ref::<Struct>().filter(|s| s.hash == *hash) {
+            return Ok(&s.values)
+        }
+        Ok(values)
+    }
+
+    fn fields_mut(&mut self, object: u32) -> Result<&mut [Box<dyn DeRszInstance>]> {
+        let (hash, values) = self.structs.get_mut(object as usize)
+            .ok_or(ErrorKind::InvalidObjectIndex { idx: object, hash: 0 })?;
+        let wrapped = values.len() == 1 && values[0].as_any().downcast_ref::<Struct>().is_some_and(|s| s.hash == *hash);
+        if wrapped {
+            return Ok(&mut values[0].as_any_mut().downcast_mut::<Struct>().unwrap().values)
+        }
+        Ok(values)
+    }
+
+    /// Index of a field in the values of an instance or struct of type `hash`
+    fn field_index(hash: u32, name: &str, values: usize, path: &str) -> Result<usize> {
+        let desc = RszDump::get_struct(hash)?;
+        if desc.fields.len() != values {
+            return Err(invalid_path(path, &format!("{} is read as a whole and has no fields", desc.name)))
+        }
+        desc.fields.iter().position(|field| field.name == name)
+            .ok_or_else(|| invalid_path(path, &format!("{} has no field {name}", desc.name)))
+    }
+
+    /// Turns the names in a path into indices into the values, starting at the last object the path goes through
+    fn resolve(&self, mut object: u32, path: &str, segments: &[Segment]) -> Result<(u32, Vec<usize>)> {
+        let mut steps = Vec::new();
+        let mut current: Option<&dyn DeRszInstance> = None;
+        for segment in segments {
+            if let (Segment::Field(_), Some(value)) = (segment, current)
+                && let Some(next) = as_object(value) {
+                object = next.idx;
+                steps.clear();
+                current = None;
+            }
+            current = Some(match (segment, current) {
+                (Segment::Field(name), None) => {
+                    if self.extern_idxs.contains(&object) || self.structs.get(object as usize)
+                        .is_some_and(|(_, values)| matches!(values.as_slice(), [v] if v.as_any().is::<ExternObject>())) {
+                        return Err(invalid_path(path, &format!("object {object} is in another file")))
+                    }
+                    let values = self.fields(object)?;
+                    let i = Self::field_index(self.structs[object as usize].0, name, values.len(), path)?;
+                    steps.push(i);
+                    values[i].as_ref()
+                }
+                (Segment::Field(name), Some(value)) => {
+                    let s = value.as_any().downcast_ref::<Struct>()
+                        .ok_or_else(|| invalid_path(path, &format!("no field {name} on a value")))?;
+                    let i = Self::field_index(s.hash, name, s.values.len(), path)?;
+                    steps.push(i);
+                    s.values[i].as_ref()
+                }
+                (Segment::Index(i), Some(value)) => {
+                    let values = value.as_any().downcast_ref::<Vec<Box<dyn DeRszInstance>>>()
+                        .ok_or_else(|| invalid_path(path, &format!("[{i}] on a value that is not an array")))?;
+                    let value = values.get(*i)
+                        .ok_or_else(|| invalid_path(path, &format!("index {i} out of {}", values.len())))?;
+                    steps.push(*i);
+                    value.as_ref()
+                }
+                (Segment::Index(i), None) => return Err(invalid_path(path, &format!("[{i}] on an object"))),
+            });
+        }
+        Ok((object, steps))
+    }
+
+    fn walk(&self, object: u32, path: &str, steps: &[usize]) -> Result<&dyn DeRszInstance> {
+        let (first, rest) = steps.split_first().ok_or_else(|| invalid_path(path, "points at an object, not a value"))?;
+        let mut value = self.fields(object)?[*first].as_ref();
+        for i in rest {
+            let any = value.as_any();
+            value = match any.downcast_ref::<Vec<Box<dyn DeRszInstance>>>() {
+                Some(values) => values[*i].as_ref(),
+                None => any.downcast_ref::<Struct>().unwrap().values[*i].as_ref(),
+            };
+        }
+        Ok(value)
+    }
+
+    fn get_in(&self, object: u32, path: &str, segments: &[Segment]) -> Result<&dyn DeRszInstance> {
+        let (object, steps) = self.resolve(object, path, segments)?;
+        self.walk(object, path, &steps)
+    }
+
+    fn get_mut_in(&mut self, object: u32, path: &str, segments: &[Segment]) -> Result<&mut Box<dyn DeRszInstance>> {
+        let (object, steps) = self.resolve(object, path, segments)?;
+        let (first, rest) = steps.split_first().ok_or_else(|| invalid_path(path, "points at an object, not a value"))?;
+        let mut value = &mut self.fields_mut(object)?[*first];
+        for i in rest {
+            let any = value.as_any_mut();
+            value = if any.is::<Vec<Box<dyn DeRszInstance>>>() {
+                &mut any.downcast_mut::<Vec<Box<dyn DeRszInstance>>>().unwrap()[*i]
+            } else {
+                &mut any.downcast_mut::<Struct>().unwrap().values[*i]
+            };
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{game::{item_dump, GameProfile}, reerr::ReError};
+    use super::super::rszserde::DeRszRegistry;
+    use super::*;
+
+    fn dersz() -> DeRsz {
+        let mut registry = DeRszRegistry::new();
+        registry.init();
+        DeRsz::from_json(&serde_json::json!({
+            "offset": 0,
+            "roots": ["app.Root"],
+            "rsz": [{
+                "_Extra": { "_Name": "sword", "_Attack": 150 },
+                "_Values": [{ "_Name": "axe", "_Attack": 250 }, { "_Name": "bow", "_Attack": 90 }],
+            }],
+        }), Rc::new(registry)).unwrap()
+    }
+
+    fn category(e: Box<dyn std::error::Error>) -> Option<&'static str> {
+        ReError::find(e.as_ref()).map(|e| e.category())
+    }
+
+    #[test]
+    fn paths() {
+        use Segment::*;
+        assert_eq!(parse_path("root[0]._Values[12]._Name").unwrap(), [
+            Field("root".to_string()), Index(0), Field("_Values".to_string()), Index(12), Field("_Name".to_string()),
+        ]);
+        assert_eq!(parse_path("_Grid[1][2]").unwrap(), [Field("_Grid".to_string()), Index(1), Index(2)]);
+        for path in ["", ".a", "a..b", "a.", "a[x]", "a[1", "a[1]b", "a[-1]"] {
+            assert_eq!(category(parse_path(path).unwrap_err()), Some("invalid_path"), "{path}");
+        }
+    }
+
+    #[test]
+    fn get_follows_objects() {
+        GameProfile::with_rsz_dump(item_dump()).enter(|| {
+            let dersz = dersz();
+            assert_eq!(dersz.get_string("root[0]._Extra._Name").unwrap(), "sword");
+            assert_eq!(dersz.get_f64("root[0]._Values[1]._Attack").unwrap(), 90.0);
+            let axe = dersz.object_at("root[0]._Values[0]").unwrap();
+            assert_eq!(dersz.get_string(&format!("object[{axe}]._Name")).unwrap(), "axe");
+            assert_eq!(dersz.path_of(axe).as_deref(), Some("root[0]._Values[0]"));
+            assert_eq!(dersz.type_name(axe), Some("app.Item"));
+            assert_eq!(dersz.objects_of_type("app.Item").count(), 3);
+
+            for path in ["root[1]._Extra", "root[0]._Missing", "root[0]._Values[2]._Name", "root[0]._Extra[0]", "_Extra._Name"] {
+                assert_eq!(category(dersz.get(path).err().unwrap()), Some("invalid_path"), "{path}");
+            }
+        });
+    }
+
+    #[test]
+    fn set_keeps_types() {
+        GameProfile::with_rsz_dump(item_dump()).enter(|| {
+            let mut dersz = dersz();
+            dersz.set("root[0]._Values[0]._Attack", 300i32).unwrap();
+            assert_eq!(dersz.get_as::<i32>("root[0]._Values[0]._Attack").unwrap(), &300);
+            assert_eq!(category(dersz.set("root[0]._Values[0]._Attack", 300u32).unwrap_err()), Some("invalid_path"));
+            assert_eq!(dersz.get_f64("root[0]._Values[0]._Attack").unwrap(), 300.0);
+            assert_eq!(category(dersz.set("root[0]._Extra", 300i32).unwrap_err()), Some("invalid_path"));
+        });
+    }
+}
@@ -12,9 +12,9 @@ use rsz_macros::{DeRszFrom, DeRszInstance, Edit};
 use serde::{Deserialize, Serialize};
 
 use crate::edit::{Edit, RszEditCtx};
-use crate::{reerr::{self, Result, RszError}};
+use crate::{align::align_up, reerr::{ErrorKind, Result, ReError, ResultExt}};
 use crate::file_ext::*;
-use super::{dump::{enum_map, get_enum_name, get_enum_list, get_enum_val, RszDump, RszField, RszStruct}, Extern, Rsz, TypeDescriptor};
+use super::{dump::{enum_map, get_enum_name, get_enum_val, RszDump, RszField, RszStruct}, Extern, Rsz, RszHeader, TypeDescriptor};
 
 pub trait ReadSeek: Read + Seek {}
 impl<'a, T: Read + Seek> ReadSeek for T {}
@@ -25,64 +25,46 @@ pub struct RszDeserializerCtx<'a> {
     pub cur_hash: Vec<u32>,
     pub extern_slots: &'a HashMap<u32, Extern>,
     pub field: Vec<&'a RszField>,
+    /// Where every field starts in the data, in the order they are read
+    pub field_offsets: Vec<FieldOffset>,
+    /// Keep instances of types missing from the rsz dump as raw bytes instead of failing
+    pub lenient: bool,
+    /// Zero bytes at the end of the data that are not in the file
+    pub padding: u64,
     pub registry: Rc<DeRszRegistry>
 }
 
+/// A field of an instance and where its data starts, relative to the rsz data
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldOffset {
+    pub offset: u64,
+    pub object: u32,
+    pub type_name: String,
+    /// Empty for types read as a whole
+    pub field: String,
+}
+
 impl<'a> RszDeserializerCtx<'a> {
     fn get_hash(&self) -> Result<u32> {
         if let Some(hash) = self.cur_hash.last() {
             return Ok(*hash)
-        } else { return Err(RszError::InvalidRszTypeHash(0).into()) };
+        } else { return Err(ErrorKind::UnknownRszHash(0).into()) };
+    }
+    /// Reads one value of a field at its alignment. Fixed size types that read past the size the dump gives them fail
+    /// here instead of shifting every field after them
+    pub fn read_value(&mut self, field: &RszField) -> Result<Box<dyn DeRszInstance>> {
+        self.data.seek_align_up(field.align.into())?;
+        let start = self.data.tell()?;
+        let value = self.registry.get(&field.r#type)?(self)?;
+        let read = self.data.tell()? - start;
+        if read > field.size.into() && !self.registry.is_dynamic(&field.r#type) {
+            return Err(ReError::new(ErrorKind::FieldOverrun { size: field.size.into(), read }).at(start).into())
+        }
+        Ok(value)
     }
     pub fn new<T: Read + Seek + 'a>(data: T, type_descriptors: &'a Vec<TypeDescriptor>, extern_slots: &'a HashMap<u32, Extern>, roots: &'a Vec<u32>) -> Self {
         let mut registry = DeRszRegistry::new();
-        //registry.register::<Nullable>("Nullable");
-        registry.register::<u8>("U8");
-        registry.register::<u16>("U16");
-        registry.register::<u32>("U32");
-        registry.register::<u64>("U64");
-        registry.register::<i8>("S8");
-        registry.register::<i16>("S16");
-        registry.register::<i32>("S32");
-        registry.register::<i64>("S64");
-        registry.register::<u8>("F8");
-        registry.register::<u16>("F16");
-        registry.register::<f32>("F32");
-        registry.register::<f64>("F64");
-        registry.register::<String>("RuntimeType");
-        registry.register::<StringU16>("String");
-        registry.register::<StringU16>("Resource");
-        registry.register::<bool>("Bool");
-        registry.register::<UInt2>("Uint2");
-        registry.register::<UInt3>("Uint3");
-        registry.register::<UInt4>("Uint4");
-        registry.register::<Int2>("Int2");
-        registry.register::<Int3>("Int3");
-        registry.register::<Int4>("Int4");
-        registry.register::<Float2>("Float2");
-        registry.register::<Float3>("Float3");
-        registry.register::<Float4>("Float4");
-        registry.register::<Vec2>("Vec2");
-        registry.register::<Vec3>("Vec3");
-        registry.register::<Vec4>("Vec4");
-        registry.register::<Quaternion>("Quaternion");
-        registry.register::<Sphere>("Sphere");
-        registry.register::<Position>("Position");
-        registry.register::<Color>("Color");
-        registry.register::<Mat4x4>("Mat4");
-        registry.register::<Guid>("Guid");
-        registry.register::<Object>("Object");
-        registry.register::<Object>("UserData");
-        registry.register::<OBB>("OBB");
-        registry.register::<AABB>("AABB");
-        registry.register::<Data>("Data");
-        registry.register::<Range>("Range");
-        registry.register::<RangeI>("RangeI");
-        registry.register::<Rect>("Rect");
-        registry.register::<Struct>("Struct");
-        registry.register::<Guid>("GameObjectRef");
-        registry.register::<KeyFrame>("KeyFrame");
-        registry.register::<u64>("Size");
+        registry.init();
         Self {
             data: Box::new(data),
             cur_hash: Vec::new(),
@@ -90,6 +72,9 @@ impl<'a> RszDeserializerCtx<'a> {
             roots: &roots,
             extern_slots: &extern_slots,
             field: Vec::new(),
+            field_offsets: Vec::new(),
+            lenient: false,
+            padding: 0,
             registry: Rc::new(registry)
         }
     }
@@ -117,6 +102,9 @@ impl<'a> From<&'a Rsz> for RszDeserializerCtx<'a> {
             roots: &value.roots,
             extern_slots: &value.extern_slots,
             field: Vec::new(),
+            field_offsets: Vec::new(),
+            lenient: value.lenient,
+            padding: value.padding() as u64,
             registry: Rc::new(registry)
         }
     }
@@ -128,7 +116,20 @@ pub struct RszJsonSerializerCtx<'a> {
     root: Option<u32>,
     field: Option<&'a RszField>,
     objects: &'a Vec<RszFieldsValue>,
-    parent: Option<&'a RszStruct<RszField>>
+    roots: &'a [u32],
+    parent: Option<&'a RszStruct<RszField>>,
+    /// Write enums as their name and raw value instead of just the name
+    lossless: bool,
+}
+
+impl RszJsonSerializerCtx<'_> {
+    fn enum_json<T: Serialize>(&self, name: String, value: &T) -> serde_json::Value {
+        if self.lossless {
+            serde_json::json!({ "name": name, "value": value })
+        } else {
+            serde_json::json!(name)
+        }
+    }
 }
 
 
@@ -163,6 +164,7 @@ pub trait DeRszInstanceClone {
 
 pub trait DeRszInstance: Debug + DeRszInstanceClone + Any + Edit {
     fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value;
     fn to_bytes(&self, _ctx: &mut RszSerializerCtx) -> Result<()>;
 }
@@ -203,6 +205,9 @@ impl DeRszInstance for Vec<Box<dyn DeRszInstance>> {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         serde_json::Value::Array(self.iter().map(|item| {
@@ -210,7 +215,9 @@ impl DeRszInstance for Vec<Box<dyn DeRszInstance>> {
                 root: None,
                 field: ctx.field,
                 objects: ctx.objects,
+                roots: ctx.roots,
                 parent: ctx.parent,
+                lossless: ctx.lossless,
             };
             item.to_json(&new_ctx)
         }).collect())
@@ -228,6 +235,9 @@ impl<T: 'static + DeRszInstance + Debug + Serialize + Clone> DeRszInstance for V
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 
     fn to_json(&self, _ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         serde_json::json!(self)
@@ -281,6 +291,9 @@ impl<T: 'static + DeRszInstance + Debug + Clone, const N: usize> DeRszInstance f
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         let values = self.iter().map(|x| {
             x.to_json(ctx)
@@ -331,14 +344,20 @@ impl DeRszInstance for Object {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         let res: Result<&RszFieldsValue> = ctx.objects.get(ctx.root.unwrap_or(self.idx) as usize)
-            .ok_or(RszError::InvalidRszObjectIndex(self.idx, self.hash).into());
+            .ok_or(ErrorKind::InvalidObjectIndex { idx: self.idx, hash: self.hash }.into());
         let (hash, field_values) = match res {
             Ok(a) => a,
             Err(e) => {eprintln!("{:?}", e); return serde_json::Value::Null;}
         };
+        if let Some(raw) = field_values.first().and_then(|v| v.as_any().downcast_ref::<RawInstance>()) {
+            return raw.to_json_with_objects(ctx.root.unwrap_or(self.idx), ctx)
+        }
         let struct_desc = match RszDump::rsz_map().get(&hash) {
             Some(struct_desc) => struct_desc,
             None => return serde_json::Value::Null
@@ -366,17 +385,17 @@ impl DeRszInstance for Object {
                       }*/
                     if let Some(enummable) = obj.as_any().downcast_ref::<i32>() {
                         if let Some(enum_str_val) = enummable.get_enum_name(&struct_desc.name) {
-                            return (field.name.clone(), serde_json::json!(enum_str_val))
+                            return (field.name.clone(), ctx.enum_json(enum_str_val, enummable))
                         }
                     }
                     if let Some(enummable) = obj.as_any().downcast_ref::<u32>() {
                         if let Some(enum_str_val) = enummable.get_enum_name(&struct_desc.name) {
-                            return (field.name.clone(), serde_json::json!(enum_str_val))
+                            return (field.name.clone(), ctx.enum_json(enum_str_val, enummable))
                         }
                     }
                     if let Some(enummable) = obj.as_any().downcast_ref::<u64>() {
                         if let Some(enum_str_val) = enummable.get_enum_name(&struct_desc.name) {
-                            return (field.name.clone(), serde_json::json!(enum_str_val))
+                            return (field.name.clone(), ctx.enum_json(enum_str_val, enummable))
                         }
                     }
                 }
@@ -384,7 +403,9 @@ impl DeRszInstance for Object {
                     root: None,
                     field: Some(&field),
                     objects: ctx.objects,
-                    parent: Some(struct_desc)
+                    roots: ctx.roots,
+                    parent: Some(struct_desc),
+                    lossless: ctx.lossless,
                 };
                 (field.name.clone(), obj.to_json(&new_ctx))
             }).collect::<IndexMap<String, serde_json::Value>>();
@@ -399,12 +420,12 @@ impl DeRszInstance for Object {
                 if field.name.contains("EnumValue") {
                     if let Some(enummable) = obj.as_any().downcast_ref::<i32>() {
                         if let Some(enum_str_val) = enummable.get_enum_name(&types[0]) {
-                            return (field.name.clone(), serde_json::json!(enum_str_val))
+                            return (field.name.clone(), ctx.enum_json(enum_str_val, enummable))
                         }
                     }
                     if let Some(enummable) = obj.as_any().downcast_ref::<u32>() {
                         if let Some(enum_str_val) = enummable.get_enum_name(&types[0]) {
-                            return (field.name.clone(), serde_json::json!(enum_str_val))
+                            return (field.name.clone(), ctx.enum_json(enum_str_val, enummable))
                         }
                     }
                 }
@@ -412,7 +433,9 @@ impl DeRszInstance for Object {
                     root: None,
                     field: Some(&field),
                     objects: ctx.objects,
-                    parent: Some(struct_desc)
+                    roots: ctx.roots,
+                    parent: Some(struct_desc),
+                    lossless: ctx.lossless,
                 };
                 (field.name.clone(), obj.to_json(&new_ctx))
             }).collect::<IndexMap<String, serde_json::Value>>();
@@ -476,7 +499,9 @@ impl DeRszInstance for Object {
                 root: None,
                 field: Some(&field),
                 objects: ctx.objects,
-                parent: Some(struct_desc)
+                roots: ctx.roots,
+                parent: Some(struct_desc),
+                lossless: ctx.lossless,
             };
             Some((field.name.clone(), obj.to_json(&new_ctx)))
         }).collect::<IndexMap<String, serde_json::Value>>();
@@ -501,6 +526,17 @@ impl<'a> DeRszType<'a> for Object {
 
 impl RszFromJson for Object {
     fn from_json(data: &serde_json::Value, ctx: &mut RszJsonDeserializerCtx) -> Result<Self> where Self: Sized {
+        if data.get("extern_path").is_some() {
+            let idx = ctx.objects.len() as u32;
+            let extern_obj = ExternObject::from_json(data, ctx, idx)?;
+            ctx.objects.push((extern_obj.object.hash, vec![Box::new(extern_obj)]));
+            return Ok(Object { hash: ctx.hash, idx })
+        }
+        if data.get("raw_type").is_some() {
+            let raw = RawInstance::from_json(data, ctx)?;
+            ctx.objects.push((raw.hash, vec![Box::new(raw)]));
+            return Ok(Object { hash: ctx.hash, idx: ctx.objects.len() as u32 - 1 })
+        }
         let parent_struct = RszDump::get_struct(ctx.hash)?;
         // println!("{}: {:?}", parent_struct.name, data);
         let field_name = &ctx.field.unwrap().original_type;
@@ -527,23 +563,157 @@ impl RszFromJson for Object {
 }
 
 
+/// An instance that lives in another user file, only its type and path are stored in the extern slot table
 #[derive(Debug, Clone)]
 pub struct ExternObject {
     path: String,
     pub object: Object,
 }
 
+impl ExternObject {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// `{"extern_path": ..., "extern_type": ...}`, the type falls back to the type of the field
+    fn from_json(data: &serde_json::Value, ctx: &RszJsonDeserializerCtx, idx: u32) -> Result<Self> {
+        let path = data["extern_path"].as_str()
+            .ok_or(ErrorKind::InvalidSlot(format!("extern_path should be a string, found {}", data["extern_path"])))?;
+        let hash = match data.get("extern_type").and_then(|t| t.as_str()) {
+            Some(name) => *RszDump::name_map().get(&name.to_string())
+                .ok_or(ErrorKind::InvalidSlot(format!("Unknown extern type {name}")))?,
+            None => *ctx.field.and_then(|field| field.get_type_hash())
+                .ok_or(ErrorKind::InvalidSlot(format!("Could not find the type of extern {path}")))?,
+        };
+        Ok(Self { path: path.to_string(), object: Object { hash, idx } })
+    }
+}
+
 impl DeRszInstance for ExternObject {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, _ctx: &RszJsonSerializerCtx) -> serde_json::Value {
+        let extern_type = RszDump::get_struct(self.object.hash).map(|s| s.name.clone()).ok();
         serde_json::json!({
             "extern_path": self.path,
+            "extern_type": extern_type,
         })
     }
+    // extern instances have no data, the slot table written by Rsz::to_buf describes them
     fn to_bytes(&self, _ctx: &mut RszSerializerCtx) -> Result<()> {
-        todo!()
+        Ok(())
+    }
+}
+
+/// Instance of a type missing from the rsz dump, kept as the bytes it was read from
+#[derive(Debug, Clone)]
+pub struct RawInstance {
+    pub hash: u32,
+    pub crc: u32,
+    pub data: Vec<u8>,
+}
+
+impl RawInstance {
+    /// `{"raw_type": ..., "raw_crc": ..., "raw_data": ...}`, all of them hex. The `raw_objects` are added to the objects
+    /// first, so they get back the indices the raw data points at
+    fn from_json(data: &serde_json::Value, ctx: &mut RszJsonDeserializerCtx) -> Result<Self> {
+        for object in data.get("raw_objects").and_then(|o| o.as_array()).into_iter().flatten() {
+            let value = &object["value"];
+            if value.get("extern_path").is_some() {
+                let extern_obj = ExternObject::from_json(value, ctx, ctx.objects.len() as u32)?;
+                ctx.objects.push((extern_obj.object.hash, vec![Box::new(extern_obj)]));
+                continue
+            }
+            let name = object["type"].as_str()
+                .ok_or(ErrorKind::InvalidValue(format!("type of raw object should be a string, found {}", object["type"])))?;
+            let hash = *RszDump::name_map().get(&name.to_string()).ok_or(ErrorKind::InvalidValue(format!("Unknown type {name}")))?;
+            let mut new_ctx = RszJsonDeserializerCtx {
+                hash,
+                field: None,
+                objects: ctx.objects,
+                registry: ctx.registry.clone(),
+            };
+            let r#struct = Struct::from_json(value, &mut new_ctx)?;
+            ctx.objects.push((hash, vec![Box::new(r#struct)]));
+        }
+        let hex = |name: &str| data[name].as_str()
+            .ok_or(ErrorKind::InvalidValue(format!("{name} should be a hex string, found {}", data[name])));
+        let raw_data = hex("raw_data")?;
+        let bytes = raw_data.as_bytes().chunks(2)
+            .map(|c| std::str::from_utf8(c).ok().filter(|c| c.len() == 2).and_then(|c| u8::from_str_radix(c, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(ErrorKind::InvalidValue(format!("raw_data is not hex: {raw_data}")))?;
+        Ok(Self {
+            hash: u32::from_str_radix(hex("raw_type")?, 16)?,
+            crc: u32::from_str_radix(hex("raw_crc")?, 16)?,
+            data: bytes,
+        })
+    }
+}
+
+impl RawInstance {
+    /// The objects between the previous raw instance and this one that nothing else points at are only reachable
+    /// through the raw data, they are written with it so they aren't lost
+    fn to_json_with_objects(&self, idx: u32, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
+        let mut referenced = HashSet::new();
+        for (_, values) in ctx.objects {
+            for value in values {
+                collect_objects(value.as_ref(), &mut referenced);
+            }
+        }
+        let is_raw = |i: u32| ctx.objects[i as usize].1.first().is_some_and(|v| v.as_any().is::<RawInstance>());
+        let first = (1..idx).rev().find(|i| is_raw(*i)).map_or(1, |i| i + 1);
+        let objects: Vec<serde_json::Value> = (first..idx)
+            .filter(|i| !referenced.contains(i) && !ctx.roots.contains(i))
+            .filter_map(|i| {
+                let hash = ctx.objects[i as usize].0;
+                let name = &RszDump::get_struct(hash).ok()?.name;
+                let new_ctx = RszJsonSerializerCtx { root: Some(i), field: None, objects: ctx.objects, roots: ctx.roots, parent: None, lossless: ctx.lossless };
+                Some(serde_json::json!({ "type": name, "value": Object { hash, idx: i }.to_json(&new_ctx) }))
+            })
+            .collect();
+        let mut json = self.to_json(ctx);
+        if !objects.is_empty() {
+            json["raw_objects"] = serde_json::Value::Array(objects);
+        }
+        json
+    }
+}
+
+/// Indices of the objects a value points at
+fn collect_objects(value: &dyn DeRszInstance, objects: &mut HashSet<u32>) {
+    let any = value.as_any();
+    if let Some(object) = any.downcast_ref::<Object>() {
+        objects.insert(object.idx);
+    } else if let Some(values) = any.downcast_ref::<Vec<Box<dyn DeRszInstance>>>() {
+        values.iter().for_each(|value| collect_objects(value.as_ref(), objects));
+    } else if let Some(s) = any.downcast_ref::<Struct>() {
+        s.values.iter().for_each(|value| collect_objects(value.as_ref(), objects));
+    }
+}
+
+impl DeRszInstance for RawInstance {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn to_json(&self, _ctx: &RszJsonSerializerCtx) -> serde_json::Value {
+        serde_json::json!({
+            "raw_type": format!("{:08x}", self.hash),
+            "raw_crc": format!("{:08x}", self.crc),
+            "raw_data": self.data.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+        })
+    }
+    // the bytes include the padding before the instance, so they go back exactly where they were read from
+    fn to_bytes(&self, ctx: &mut RszSerializerCtx) -> Result<()> {
+        ctx.data.write_all(&self.data)?;
+        Ok(())
     }
 }
 
@@ -558,6 +728,10 @@ type JsonDeserializerFn = fn(&serde_json::Value, &mut RszJsonDeserializerCtx) ->
 pub struct DeRszRegistry {
     deserializers: HashMap<&'static str, DeserializerFn>,
     serializers: HashMap<&'static str, JsonDeserializerFn>,
+    /// Types whose size depends on the data, the size in the dump is only their header
+    dynamic: HashSet<&'static str>,
+    /// Types read as a whole instance instead of field by field, with their alignment
+    instances: HashMap<&'static str, u64>,
 }
 
 impl DeRszRegistry {
@@ -575,9 +749,6 @@ impl DeRszRegistry {
         self.register::<u16>("F16");
         self.register::<f32>("F32");
         self.register::<f64>("F64");
-        self.register::<String>("RuntimeType");
-        self.register::<StringU16>("String");
-        self.register::<StringU16>("Resource");
         self.register::<bool>("Bool");
         self.register::<UInt2>("Uint2");
         self.register::<UInt3>("Uint3");
@@ -605,18 +776,23 @@ impl DeRszRegistry {
         self.register::<Range>("Range");
         self.register::<RangeI>("RangeI");
         self.register::<Rect>("Rect");
-        self.register::<Struct>("Struct");
         self.register::<Guid>("GameObjectRef");
         self.register::<KeyFrame>("KeyFrame");
         self.register::<u64>("Size");
-        self.register::<AnimationCurve>("via.AnimationCurve");
-        self.register::<AnimationCurve3D>("via.AnimationCurve3D");
+        self.register_dynamic::<String>("RuntimeType");
+        self.register_dynamic::<StringU16>("String");
+        self.register_dynamic::<StringU16>("Resource");
+        self.register_dynamic::<Struct>("Struct");
+        self.register_instance::<AnimationCurve>("via.AnimationCurve", 4);
+        self.register_instance::<AnimationCurve3D>("via.AnimationCurve3D", 4);
         //self.register::<Capsule>("Capsule");
     }
     pub fn new() -> Self {
         Self {
             deserializers: HashMap::new(),
             serializers: HashMap::new(),
+            dynamic: HashSet::new(),
+            instances: HashMap::new(),
         }
     }
     fn register<T>(&mut self, type_id: &'static str)
@@ -630,57 +806,214 @@ impl DeRszRegistry {
             Ok(Box::new(T::from_json(data, ctx)?))
         });
     }
+    fn register_dynamic<T>(&mut self, type_id: &'static str)
+    where
+        T: for<'a> DeRszType<'a> + RszFromJson + DeRszInstance + 'static
+    {
+        self.register::<T>(type_id);
+        self.dynamic.insert(type_id);
+    }
+    fn register_instance<T>(&mut self, type_id: &'static str, align: u64)
+    where
+        T: for<'a> DeRszType<'a> + RszFromJson + DeRszInstance + 'static
+    {
+        self.register::<T>(type_id);
+        self.instances.insert(type_id, align);
+    }
+    pub fn is_dynamic(&self, name: &str) -> bool {
+        self.dynamic.contains(name)
+    }
+    /// Deserializer and alignment of a type that is read as a whole instance
+    pub fn get_instance(&self, name: &str) -> Option<(DeserializerFn, u64)> {
+        let align = self.instances.get(name)?;
+        Some((self.deserializers.get(name).copied()?, *align))
+    }
     pub fn get(&self, name: &str) -> Result<DeserializerFn> {
         let de_fn = self.deserializers.get(name).copied();
-        de_fn.ok_or(RszError::UnsetDeserializer(name.to_string()).into())
+        de_fn.ok_or(ErrorKind::UnsetDeserializer(name.to_string()).into())
     }
     pub fn get_se(&self, name: &str) -> Result<JsonDeserializerFn> {
         let se_fn = self.serializers.get(name).copied();
-        se_fn.ok_or(RszError::UnsetSerializer(name.to_string()).into())
+        se_fn.ok_or(ErrorKind::UnsetSerializer(name.to_string()).into())
     }
 }
 
 #[derive(Debug)]
 pub struct DeRsz {
     pub offset: usize,
+    /// Rsz version the block is written with
+    pub version: u32,
     pub roots: Vec<u32>,
     pub structs: Vec<RszFieldsValue>,
     pub extern_idxs: HashSet<u32>,
 }
 
 
+impl DeRsz {
+    /// Type name and json of every root object, lossless keeps the raw value of enums
+    pub fn root_values(&self, lossless: bool) -> Vec<(String, serde_json::Value)> {
+        self.roots.iter().map(|root| {
+            let ctx = RszJsonSerializerCtx {root: Some(*root), field: None, objects: &self.structs, roots: &self.roots, parent: None, lossless};
+            let hash = self.structs[*root as usize].0;
+            // raw instances have no name, they are written with their hash
+            let name = RszDump::get_struct(hash).map(|desc| desc.name.clone()).unwrap_or_else(|_| format!("{hash:08x}"));
+            if let Some(raw) = self.structs[*root as usize].1.first().and_then(|v| v.as_any().downcast_ref::<RawInstance>()) {
+                return (name, raw.to_json_with_objects(*root, &ctx))
+            }
+            let obj = Object {hash: self.structs[*root as usize].0, idx: *root as u32};
+            //let data = ctx.objects[*root as usize].1.to_json(&ctx);
+            (name, obj.to_json(&ctx))
+        }).collect()
+    }
+
+    pub fn to_json(&self, lossless: bool) -> serde_json::Value {
+        let (roots, rsz): (Vec<_>, Vec<_>) = self.root_values(lossless).into_iter().unzip();
+        serde_json::json!({
+            "offset": self.offset,
+            "version": self.version,
+            "roots": roots,
+            "rsz": rsz,
+        })
+    }
+}
+
 impl Serialize for DeRsz {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer {
-            #[derive(Serialize)]
-            struct Wrapped {
-                offset: usize,
-                roots: Vec<&'static str>,
-                rsz: Vec<serde_json::Value>
+            self.to_json(false).serialize(serializer)
+    }
+}
+
+/// Objects point at another instance of the same rsz block
+fn check_object_index(ctx: &mut RszDeserializerCtx, value: &dyn DeRszInstance) -> Result<()> {
+    if let Some(object) = value.as_any().downcast_ref::<Object>()
+        && object.idx as usize >= ctx.type_descriptors.len() {
+        let offset = ctx.data.tell()? - 4;
+        return Err(ReError::new(ErrorKind::InvalidObjectIndex { idx: object.idx, hash: object.hash }).at(offset).into())
+    }
+    Ok(())
+}
+
+/// Reads the fields of instance `i`, whose type is known
+fn read_instance(ctx: &mut RszDeserializerCtx, i: usize, hash: u32, struct_type: &'static RszStruct<RszField>) -> Result<RszFieldsValue> {
+    #[cfg(debug_assertions)]
+    log::debug!("\nDeserializing: {struct_type:?}");
+
+    let mut field_values: RszFieldsValue = (hash, Vec::new());
+    if let Some((deser, align)) = ctx.registry.get_instance(&struct_type.name) {
+        ctx.data.seek_align_up(align)?;
+        let offset = ctx.data.tell()?;
+        ctx.field_offsets.push(FieldOffset { offset, object: i as u32, type_name: struct_type.name.clone(), field: String::new() });
+        let val = deser(ctx).field(&struct_type.name)?;
+        field_values.1.push(val);
+        return Ok(field_values)
+    }
+    for field in &struct_type.fields {
+        let field_hash = field.get_type_hash();
+        if let Some(field_hash) = field_hash {
+            ctx.cur_hash.push(*field_hash);
+        } else {
+            log::warn!("Could not find type hash for {} where native = {}", field.name, field.native);
+        }
+        ctx.field.push(field);
+        #[cfg(debug_assertions)]
+        log::debug!("Deserializing field: {field:?}");
+        let offset = ctx.data.tell()?;
+        ctx.field_offsets.push(FieldOffset { offset, object: i as u32, type_name: struct_type.name.clone(), field: field.name.clone() });
+        if field.array {
+            ctx.data.seek_align_up(4)?;
+            let len = ctx.data.read_u32()?;
+            #[cfg(debug_assertions)]
+            log::debug!("arr {len}");
+            let mut vals = Vec::new();
+            for _i in 0..len {
+                let x = ctx.read_value(field).field(&format!("{}.{}[{_i}]", struct_type.name, field.name))?;
+                check_object_index(ctx, x.as_ref())?;
+                vals.push(x);
             }
-            let mut wrapped = Wrapped{offset: self.offset, roots: Vec::new(), rsz: Vec::new()};
-            for root in &self.roots {
-                let ctx = RszJsonSerializerCtx {root: Some(*root), field: None, objects: &self.structs, parent: None};
-                let hash = self.structs[*root as usize].0;
-                //println!("{hash}");
-                let name = &RszDump::get_struct(hash).unwrap().name;
-                //println!("{name:?}");
-                wrapped.roots.push(name);
-                let obj = Object {hash: self.structs[*root as usize].0, idx: *root as u32};
-                //let data = ctx.objects[*root as usize].1.to_json(&ctx);
-                let data = obj.to_json(&ctx);
-                wrapped.rsz.push(data);
+            field_values.1.push(Box::new(vals));
+        } else {
+            let x = ctx.read_value(field).field(&format!("{}.{}", struct_type.name, field.name))?;
+            check_object_index(ctx, x.as_ref())?;
+            field_values.1.push(x);
+        }
+        ctx.field.pop();
+        if field_hash.is_some() {
+            ctx.cur_hash.pop();
+        }
+        #[cfg(debug_assertions)]
+        log::debug!("{:?}", field_values.1.last());
+    }
+    Ok(field_values)
+}
+
+/// Offsets tried for the end of an unknown instance, each one reads every later instance again
+const MAX_EXTENT_CANDIDATES: u64 = 1024;
+
+/// Where the data of the unknown instance `idx` ends. Without its layout the size is unknown, so this is the
+/// start of the next instance with fields: the first offset from which every later instance reads up to the end of
+/// the data exactly. Fails if none of the first `MAX_EXTENT_CANDIDATES` offsets fits, or if a later instance is of
+/// an unknown type too
+fn raw_extent(ctx: &mut RszDeserializerCtx, idx: usize, hash: u32) -> Result<u64> {
+    let start = ctx.data.tell()?;
+    let end = ctx.data.seek(SeekFrom::End(0))? - ctx.padding;
+    let extent_error = |next: usize| -> Box<dyn std::error::Error> {
+        ReError::new(ErrorKind::UnknownRszExtent { hash, idx: idx as u32, next: next as u32 }).at(start).into()
+    };
+    let mut first_align = None;
+    for next in idx + 1..ctx.type_descriptors.len() {
+        if ctx.extern_slots.contains_key(&(next as u32)) {
+            continue
+        }
+        let next_hash = ctx.type_descriptors[next].hash;
+        let Some(struct_type) = RszDump::rsz_map().get(&next_hash) else {
+            return Err(extent_error(next))
+        };
+        first_align = match (ctx.registry.get_instance(&struct_type.name), struct_type.fields.first()) {
+            (Some((_, align)), _) => Some((next, align)),
+            (None, Some(field)) if field.array => Some((next, 4)),
+            (None, Some(field)) => Some((next, field.align.into())),
+            (None, None) => continue,
+        };
+        break
+    }
+    // every instance after this one is empty, so the rest of the data is this one
+    let Some((next, align)) = first_align else {
+        ctx.data.seek(SeekFrom::Start(start))?;
+        return Ok(end)
+    };
+    let (cur_hash, field, field_offsets) = (ctx.cur_hash.len(), ctx.field.len(), ctx.field_offsets.len());
+    let mut found = None;
+    let mut candidate = align_up(start, align);
+    let last = end.min(candidate + align * (MAX_EXTENT_CANDIDATES - 1));
+    while candidate <= last {
+        ctx.data.seek(SeekFrom::Start(candidate))?;
+        let fits = (next..ctx.type_descriptors.len()).try_for_each(|i| {
+            if ctx.extern_slots.contains_key(&(i as u32)) {
+                return Ok(())
             }
-            wrapped.serialize(serializer)
+            let hash = ctx.type_descriptors[i].hash;
+            read_instance(ctx, i, hash, RszDump::get_struct(hash)?).map(|_| ())
+        }).is_ok() && ctx.data.tell()? == end;
+        ctx.cur_hash.truncate(cur_hash);
+        ctx.field.truncate(field);
+        ctx.field_offsets.truncate(field_offsets);
+        if fits {
+            found = Some(candidate);
+            break
+        }
+        candidate += align;
     }
+    ctx.data.seek(SeekFrom::Start(start))?;
+    found.ok_or_else(|| extent_error(next))
 }
 
 impl<'a> DeRszType<'a> for DeRsz {
     fn from_bytes(ctx: &'a mut RszDeserializerCtx) -> Result<Self> {
         let mut structs: Vec<RszFieldsValue> = Vec::new();
         let mut extern_idxs: Vec<u32> = Vec::new();
-        for (i, &TypeDescriptor { hash, crc: _crc }) in ctx.type_descriptors.clone().iter().enumerate() {
+        for (i, &TypeDescriptor { hash, crc }) in ctx.type_descriptors.clone().iter().enumerate() {
             if let Some(_slot_extern) = ctx.extern_slots.get(&u32::try_from(i)?) {
                 extern_idxs.push(i as u32);
                 let eobj: Box<dyn DeRszInstance> = Box::new(ExternObject {
@@ -690,91 +1023,37 @@ impl<'a> DeRszType<'a> for DeRsz {
                 structs.push((hash, vec![eobj]));
                 continue;
             } else {
-                let struct_type = match RszDump::rsz_map().get(&hash) {
-                    Some(x) => Ok(x),
-                    None => Err(Box::new(reerr::FileParseError::InvalidRszTypeHash(hash)))
-                }?;
-                #[cfg(debug_assertions)]
-                log::debug!("\nDeserializing: {struct_type:?}");
-
-                let mut field_values: RszFieldsValue = (hash, Vec::new());
-                if let Ok(deser) = ctx.registry.get(&struct_type.name) {
-                    ctx.data.seek_align_up(4)?; // i dont have proper field alignment, would have
-                                                // to add with a macro i think (easiest)
-                    //println!("{:?}, {:?}, {:?}", ctx.data.read_u8_n(256), half::f16::from_bits(u16::from_le_bytes([128, 63])), half::f16::from_bits(u16::from_le_bytes([64, 86])));
-                    let val = deser(ctx)?;
-                    field_values.1.push(val);
-                    structs.push(field_values);
-                    continue
-                }
-                for field in &struct_type.fields {
-                    if let Some(field_hash) = field.get_type_hash() {
-                        ctx.cur_hash.push(*field_hash);
-                    } else {
-                        log::warn!("Could not find type hash for {} where native = {}", field.name, field.native);
-                    }
-                    ctx.field.push(&field);
-                    #[cfg(debug_assertions)]
-                    log::debug!("Deserializing field: {field:?}");
-                    /*let pos = ctx.data.tell()?;
-                      let end = ctx.data.seek(SeekFrom::End(0))?;
-                      let len_left = end - pos;
-                      ctx.data.seek(SeekFrom::Start(pos))?;
-                      println!("{:x}, len {}", ctx.data.tell()?, len_left);*/
-                    if field.name == "_ShellList" && struct_type.name == "app.user_data.EmParamLegendary" {
-                        //continue;
-                    }
-                    //println!("0x{:x}", ctx.data.tell()?);
-                    if field.array {
-                        ctx.data.seek_align_up(4)?;
-                        //println!("0x{:x}", ctx.data.tell()?);
-                        let len = ctx.data.read_u32().unwrap_or_else(|e| {eprintln!("{e:?}, replacing arr len with zero"); 0});
-                        #[cfg(debug_assertions)]
-                        log::debug!("arr {len}");
-                        if len > 0 {
-                            ctx.data.seek_align_up(field.align.into())?;
-                        }
-                        let mut vals = Vec::new();
-                        for _i in 0..len {
-                            ctx.data.seek_align_up(field.align.into())?;
-                            let pos = ctx.data.tell()?;
-                            //println!("idx: {_i}, pos: {}, data {:?}", ctx.data.tell()?, ctx.data.read_u8_n(32)?);
-                            ctx.data.seek(SeekFrom::Start(pos))?;
-                            let dersz_fn = ctx.registry.get(field.r#type.as_str())?;
-                            let x: Box<dyn DeRszInstance> = dersz_fn(ctx)?;
-                            vals.push(x);
-                        }
-                        field_values.1.push(Box::new(vals));
-                    } else {
-                        ctx.data.seek_align_up(field.align.into())?;
-                        let pos = ctx.data.tell()?;
-                        //println!("pos: {}, data {:?}", ctx.data.tell()?, ctx.data.read_u8_n(32)?);
-                        ctx.data.seek(SeekFrom::Start(pos))?;
-                        let dersz_fn = ctx.registry.get(field.r#type.as_str())?;
-                        let x: Box<dyn DeRszInstance> = dersz_fn(ctx)?;
-                        if let Some(x) = x.as_any().downcast_ref::<Object>() {
-                            if x.idx > 1000000 { //choose a big number that works
-                                ctx.data.seek_relative(-4)?;
-                            }
-                        }
-                        field_values.1.push(x);
+                let Some(struct_type) = RszDump::rsz_map().get(&hash) else {
+                    let offset = ctx.data.tell()?;
+                    if !ctx.lenient {
+                        return Err(ReError::new(ErrorKind::UnknownRszHash(hash)).at(offset).into())
                     }
-                    #[cfg(debug_assertions)]
-                    log::debug!("{:?}", field_values.1.last());
-                }
-                structs.push(field_values);
+                    let end = raw_extent(ctx, i, hash)?;
+                    log::warn!("Keeping instance {i} of unknown type {hash:08x} as raw data");
+                    ctx.field_offsets.push(FieldOffset { offset, object: i as u32, type_name: format!("{hash:08x}"), field: String::new() });
+                    let mut data = vec![0; (end - offset) as usize];
+                    ctx.data.read_exact(&mut data)?;
+                    structs.push((hash, vec![Box::new(RawInstance { hash, crc, data })]));
+                    continue
+                };
+                structs.push(read_instance(ctx, i, hash, struct_type)?);
             }
         }
 
         //println!("{structs:#?}, {extern_idxs:#?}");
 
-        Ok(Self { offset: 0, roots: ctx.roots.clone(), structs, extern_idxs: HashSet::new() })
+        Ok(Self { offset: 0, version: RszHeader::VERSION, roots: ctx.roots.clone(), structs, extern_idxs: extern_idxs.into_iter().collect() })
     }
 }
 
 impl DeRsz {
     pub fn from_json(data: &serde_json::Value, registry: Rc<DeRszRegistry>) -> Result<Self> {
         let offset = data.get("offset").unwrap().as_u64().expect("offset should be an integer") as usize;
+        // dumps from before the version was written are all the default version
+        let version = match data.get("version") {
+            Some(version) => version.as_u64().ok_or("version should be an integer")? as u32,
+            None => RszHeader::VERSION,
+        };
         let root_data = data.get("roots").unwrap();
         let roots_types: Vec<String> = serde_json::from_value(root_data.clone())?;
         let rszs_data = data.get("rsz").unwrap().as_array().expect("rszs should be in an array");
@@ -783,6 +1062,18 @@ impl DeRsz {
         let mut objects: Vec<RszFieldsValue> = Vec::new();
         objects.push((0, vec![]));
         for (rsz_data, root_type) in rszs_data.iter().zip(roots_types) {
+            if rsz_data.get("raw_type").is_some() {
+                let mut ctx = RszJsonDeserializerCtx {
+                    hash: 0,
+                    objects: &mut objects,
+                    registry: registry.clone(),
+                    field: None,
+                };
+                let raw = RawInstance::from_json(rsz_data, &mut ctx)?;
+                objects.push((raw.hash, vec![Box::new(raw)]));
+                roots.push(objects.len() as u32 - 1);
+                continue
+            }
             // println!("{root_type}");
             let hash = *RszDump::name_map().get(&root_type).unwrap();
             let mut ctx = RszJsonDeserializerCtx {
@@ -792,15 +1083,21 @@ impl DeRsz {
                 field: None,
             };
             let val = Struct::from_json(rsz_data, &mut ctx)?;
-            ctx.objects.push((val.hash, val.values));
+            // stored whole like nested objects, writing the rsz only writes the first value
+            ctx.objects.push((val.hash, vec![Box::new(val)]));
             roots.push(ctx.objects.len() as u32 - 1);
         }
         // println!("{:#?}", objects);
+        let extern_idxs = objects.iter().enumerate()
+            .filter(|(_, (_, values))| values.first().is_some_and(|v| v.as_any().is::<ExternObject>()))
+            .map(|(i, _)| i as u32)
+            .collect();
         Ok(Self {
             offset,
+            version,
             roots,
             structs: objects,
-            extern_idxs: HashSet::new()
+            extern_idxs
         })
     }
 }
@@ -936,6 +1233,9 @@ macro_rules! derive_dersz_instance {
             fn as_any(&self) -> &dyn Any {
                 self
             }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
             fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
                 serde_json::json!(self)
             }
@@ -1007,12 +1307,15 @@ macro_rules! derive_dersz_type_enum{
             fn as_any(&self) -> &dyn Any {
                 self
             }
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
             fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
                 return ctx.field.map_or(serde_json::json!(self), |field| {
                     let tmp = field.original_type.replace("[]", "");
                     let enum_str_val = self.get_enum_name(&tmp);
                     if let Some(enum_str_val) = enum_str_val {
-                        serde_json::json!(enum_str_val)
+                        ctx.enum_json(enum_str_val, self)
                     } else {
                         serde_json::json!(self)
                     }
@@ -1036,6 +1339,10 @@ macro_rules! derive_dersz_type_enum{
                             }
                         };
                         if enum_map().get(&tmp).is_some() || tmp.contains("Serializable") {
+                            // lossless dumps have the raw value next to the name
+                            if let Some(value) = data.get("value").or(data.is_number().then_some(data)) {
+                                return Ok(serde_json::from_value(value.clone())?);
+                            }
                             let enum_str_val = data.as_str().unwrap();
                             let enum_val = enum_val_from_str(&tmp, &enum_str_val);
                             return Ok(enum_val as $rsz_type);
@@ -1073,6 +1380,9 @@ impl DeRszInstance for bool {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, _ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         serde_json::json!(self)
     }
@@ -1086,7 +1396,7 @@ impl<'a> DeRszType<'a> for bool {
     fn from_bytes(ctx: &'a mut RszDeserializerCtx) -> Result<Self> where Self: Sized {
         let v = ctx.data.read_u8()?;
         if v > 1 {
-            return Err(Box::new(crate::reerr::FileParseError::InvalidBool(v)))
+            return Err(Box::new(ReError::new(ErrorKind::InvalidBool(v)).at(ctx.data.tell()? - 1)))
         }
         Ok(v != 0)
     }
@@ -1188,6 +1498,9 @@ impl DeRszInstance for Option<Box<dyn DeRszInstance>> {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         if let Some(val) = self { serde_json::json!(val.to_json(ctx)) }
         else { serde_json::Value::Null }
@@ -1206,6 +1519,9 @@ impl DeRszInstance for Nullable {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         serde_json::json!({
             "has_value": self.has_value,
@@ -1222,7 +1538,7 @@ impl<'a> DeRszType<'a> for Nullable {
         let hash = *ctx.cur_hash.last().unwrap();
         let struct_desc = match RszDump::rsz_map().get(&hash) {
             Some(struct_desc) => struct_desc,
-            None => return Err(Box::new(reerr::FileParseError::InvalidRszTypeHash(hash)))
+            None => return Err(ErrorKind::UnknownRszHash(hash).into())
         };
         let has_value = bool::from_bytes(ctx)?;
         if has_value {
@@ -1241,6 +1557,9 @@ impl DeRszInstance for String {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
     fn to_json(&self, _ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         serde_json::json!(self)
     }
@@ -1291,7 +1610,7 @@ struct Data {
 impl<'a> DeRszType<'a> for Data {
     fn from_bytes(ctx: &'a mut RszDeserializerCtx) -> Result<Self> where Self: Sized {
         let len = ctx.field.last()
-            .ok_or(RszError::MissingFieldDescription(format!("{}, {}",file!(), line!())))?.size;
+            .ok_or(ReError::new(ErrorKind::MissingFieldDescription))?.size;
         let buf = ctx.data.read_u8_n(len as usize)?;
         Ok(Data {
             data: buf.to_vec()
@@ -1333,6 +1652,9 @@ impl DeRszInstance for StructData {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         //println!("{:?}", ctx.field);
@@ -1358,6 +1680,9 @@ impl DeRszInstance for StructData {
             type_descriptors: &fake_types,
             cur_hash,
             field,
+            field_offsets: Vec::new(),
+            lenient: false,
+            padding: 0,
         };
         if let Ok(dersz_fn) = dersz_fn {
             let x: Box<dyn DeRszInstance> = dersz_fn(&mut de_ctx).unwrap();
@@ -1385,6 +1710,9 @@ impl DeRszInstance for Struct {
     fn as_any(&self) -> &dyn Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 
     fn to_json(&self, ctx: &RszJsonSerializerCtx) -> serde_json::Value {
         let struct_desc = match RszDump::rsz_map().get(&self.hash) {
@@ -1397,7 +1725,9 @@ impl DeRszInstance for Struct {
                 root: None,
                 field: Some(&field),
                 objects: ctx.objects,
-                parent: Some(struct_desc)
+                roots: ctx.roots,
+                parent: Some(struct_desc),
+                lossless: ctx.lossless,
             };
             (field.name.clone(), obj.to_json(&new_ctx))
         }).collect::<IndexMap<String, serde_json::Value>>();
@@ -1448,23 +1778,19 @@ impl<'a> DeRszType<'a> for Struct {
                 if field.array {
                     ctx.data.seek_align_up(4)?;
                     let len = ctx.data.read_u32()?;
-                    ctx.data.seek_align_up(field.align.into())?;
                     let mut vals = Vec::new();
-                    for _ in 0..len {
-                        let dersz_fn = ctx.registry.get(&field.r#type)?;
-                        let x: Box<dyn DeRszInstance> = dersz_fn(ctx)?;
+                    for i in 0..len {
+                        let x = ctx.read_value(field).field(&format!("{}[{i}]", field.name))?;
                         vals.push(x);
                     }
                     values.push(Box::new(vals))
                 } else {
-                    ctx.data.seek_align_up(field.align.into())?;
-                    let dersz_fn = ctx.registry.get(&field.r#type)?;
-                    let x: Box<dyn DeRszInstance> = dersz_fn(ctx)?;
+                    let x = ctx.read_value(field).field(&field.name)?;
                     values.push(x);
                 }
                 ctx.cur_hash.pop();
             } else {
-                return Err(format!("InvalidRszTypeName {}", field.name).into())
+                return Err(Box::new(ReError::new(ErrorKind::UnknownFieldType(field.r#type.clone())).field(&field.name)))
             }
         }
         Ok(Self {
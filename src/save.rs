@@ -1,10 +1,10 @@
 pub mod crypt;
 pub mod types;
 
-use std::{fs::File, io::{Cursor, Read, Seek, SeekFrom, Write}};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
 use fasthash::murmur3;
-use crate::{file::{Magic, StructRW}, save::types::Class};
+use crate::{file::{Magic, StructRW}, reerr::{ErrorKind, ReError}, save::types::Class};
 use fasthash::FastHash;
 
 use crate::file_ext::SeekExt;
@@ -21,75 +21,116 @@ pub struct SaveContext {
     pub key: u64,
 }
 
-impl StructRW<SaveContext> for SaveFile {
-    fn read<R: Read + Seek>(reader: &mut R, ctx: &mut SaveContext) -> crate::file::Result<Self>
-            where
-                Self: Sized {
+/// The plain 16 byte header in front of the encrypted save payload
+#[derive(Debug, Clone, Copy)]
+pub struct SaveHeader {
+    pub version: u32,
+    pub flags: u32,
+    pub unk: u32,
+}
+
+impl SaveHeader {
+    pub const SIZE: usize = 0x10;
+
+    pub fn read<R: Read + Seek>(reader: &mut R) -> crate::file::Result<Self> {
         let magic = Magic::<4>::read(reader, &mut ())?;
         if &magic != b"DSSS" {
-            return Err(format!("Magic {}, != DSSS", String::from_utf8(magic.0.to_vec())?).into())
+            return Err(Box::new(ReError::magic(b"DSSS", &magic.0).at(0)))
         }
         let version = u32::read(reader, &mut ())?;
         if version != 2 {
-            return Err(format!("Save file version incorrect I hope: {version}").into())
+            return Err(Box::new(ReError::new(ErrorKind::UnsupportedVersion { format: "save", version }).at(4)))
         }
         let flags = u32::read(reader, &mut ())?;
-        println!("Version={version}, Save Flags: {:034b}", flags); // theres flags for encryption type, compression,
-                                                // etc
         // rise doesn't look at this
-        let _save_or_user_i_think = u32::read(reader, &mut ())?;
-        let mandarin = flags & 0x10 != 0;
-        let blowfish = flags & 0x1 != 0;
-        let deflate = flags & 0x8 != 0;
+        let unk = u32::read(reader, &mut ())?;
+        Ok(Self { version, flags, unk })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> crate::file::Result<()> {
+        writer.write_all(b"DSSS")?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.flags.to_le_bytes())?;
+        writer.write_all(&self.unk.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Parses a steam id either as decimal or as hex with a 0x prefix
+pub fn parse_steamid(steamid: &str) -> crate::file::Result<u64> {
+    let steamid = if let Some(hex) = steamid.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else {
+        u64::from_str_radix(steamid, 10)
+    }?;
+    Ok(steamid)
+}
+
+impl SaveFile {
+    /// Decrypts the save payload, the result is still deflate compressed
+    pub fn decrypt<R: Read + Seek>(reader: &mut R, key: u64) -> crate::file::Result<(SaveHeader, Vec<u8>)> {
+        let header = SaveHeader::read(reader)?;
+        log::debug!("Version={}, Save Flags: {:034b}", header.version, header.flags); // theres flags for encryption type, compression,
+                                                // etc
+        let mandarin = header.flags & 0x10 != 0;
+        let blowfish = header.flags & 0x1 != 0;
+        let deflate = header.flags & 0x8 != 0;
         // 0x4 is something related to the usage of mandarin and deflate i think
-        println!("deflate={deflate}, mandarin={mandarin}, blowfish={blowfish}");
+        log::debug!("deflate={deflate}, mandarin={mandarin}, blowfish={blowfish}");
 
         let data_start = reader.tell()?;
         reader.seek(std::io::SeekFrom::End(-12))?;
         let decrypted_len = u64::read(reader, &mut ())?;
-        println!("decrypted_len={decrypted_len:x}");
+        log::debug!("decrypted_len={decrypted_len:x}");
         let end_hash = u32::read(reader, &mut ())?;
-        println!("end_hash={end_hash:x}");
+        log::debug!("end_hash={end_hash:x}");
         let len = reader.stream_position()?;
         reader.seek(SeekFrom::Start(0))?;
         let mut file_bytes: Vec<u8> = vec![];
         reader.read_to_end(&mut file_bytes)?;
         let file_hash = murmur3::Hash32::hash_with_seed(&file_bytes[..(len as usize - 4)], 0xffffffff);
         if end_hash != file_hash {
-            println!("[File Hash Check] Invalid File Hashes: target={:x}, calculated={:x}", end_hash, file_hash);
+            log::warn!("[File Hash Check] Invalid File Hashes: target={:x}, calculated={:x}", end_hash, file_hash);
         } else {
-            println!("[File Hash Check] File Hashes equal: target={:x}, calculated={:x}", end_hash, file_hash);
+            log::debug!("[File Hash Check] File Hashes equal: target={:x}, calculated={:x}", end_hash, file_hash);
         }
 
         // Decryption
         reader.seek(SeekFrom::Start(data_start))?;
         let mut encrypted = vec![];
         reader.read_to_end(&mut encrypted)?;
-        let data = if mandarin && deflate || true {
-            let key = if ctx.key == 0 {
-                Mandarin::brute_force(&encrypted, decrypted_len as u64)
-            } else {ctx.key};
-            //println!("Found key: {:#x}", key);
-            println!("{decrypted_len}");
-            let decrypted_buf = Mandarin::decrypt(&encrypted, decrypted_len as u64, key)?;
-            println!("[Decrypted]");
-            /*let encrypted_buf = Mandarin::encrypt(&decrypted_buf, key)?;
-            println!("[Re-Encrypted]");
-            let decrypted_buf = Mandarin::decrypt(&encrypted_buf, decrypted_buf.len() as u64, key)?;
-            println!("[Re-Decrypted]");
-            let mut data_cursor = Cursor::new(Vec::new());
-            data_cursor.write_all(b"DSSS")?;
-            data_cursor.write_all(&version.to_le_bytes())?;
-            data_cursor.write_all(&flags.to_le_bytes())?;
-            data_cursor.write_all(&_save_or_user_i_think.to_le_bytes())?;
-            data_cursor.write_all(&encrypted_buf)?;
-            data_cursor.write_all(&decrypted_len.to_le_bytes())?;
-            let data = &data_cursor.into_inner();
-            let file_hash = murmur3::Hash32::hash_with_seed(&data, 0xffffffff);
-            let mut f = File::create("./outputs/misc/saves/data001Slot.bin").unwrap();
-            f.write_all(&data)?;
-            f.write_all(&file_hash.to_le_bytes())?;*/
+        let key = if key == 0 {
+            Mandarin::brute_force(&encrypted, decrypted_len as u64)
+        } else {key};
+        //println!("Found key: {:#x}", key);
+        let decrypted_buf = Mandarin::decrypt(&encrypted, decrypted_len as u64, key)
+            .map_err(|e| ReError::new(ErrorKind::Decrypt(e.to_string())).at(data_start))?;
+        log::debug!("[Decrypted]");
+        Ok((header, decrypted_buf))
+    }
+
+    /// Inverse of [`SaveFile::decrypt`], builds a full save file including the trailing length and hash
+    pub fn encrypt(header: &SaveHeader, decrypted: &[u8], key: u64) -> crate::file::Result<Vec<u8>> {
+        let encrypted_buf = Mandarin::encrypt(decrypted, key)
+            .map_err(|e| ReError::new(ErrorKind::Decrypt(e.to_string())))?;
+        log::debug!("[Encrypted]");
+        let mut data_cursor = Cursor::new(Vec::new());
+        header.write(&mut data_cursor)?;
+        data_cursor.write_all(&encrypted_buf)?;
+        data_cursor.write_all(&(decrypted.len() as u64).to_le_bytes())?;
+        let mut data = data_cursor.into_inner();
+        let file_hash = murmur3::Hash32::hash_with_seed(&data, 0xffffffff);
+        data.extend(file_hash.to_le_bytes());
+        Ok(data)
+    }
+}
 
+impl StructRW<SaveContext> for SaveFile {
+    fn read<R: Read + Seek>(reader: &mut R, ctx: &mut SaveContext) -> crate::file::Result<Self>
+            where
+                Self: Sized {
+        let (_header, decrypted_buf) = Self::decrypt(reader, ctx.key)?;
+        let data = {
             // Decompression
             let mut decrypted_buf = Cursor::new(&decrypted_buf);
             let _compressed_size = u64::read(&mut decrypted_buf, &mut ())?;
@@ -103,10 +144,8 @@ impl StructRW<SaveContext> for SaveFile {
             let mut decompressor = libdeflater::Decompressor::new();
             let mut decompressed = vec![0u8; decompressed_size as usize];
             decompressor.deflate_decompress(&compressed, &mut decompressed)?;
-            println!("[Decompressed]");
+            log::debug!("[Decompressed]");
             decompressed
-        } else {
-            encrypted
         };
         let data = &mut Cursor::new(&data);
         let unk = u32::read(data, &mut ())?;
@@ -114,7 +153,7 @@ impl StructRW<SaveContext> for SaveFile {
         let unk2 = u32::read(data, &mut ())?;
         //let detail = read_value(data, FieldType::Class, None)?;
         let detail = types::Class::read(data, &mut ())?;
-        println!("{unk:#x}, {unk2:#x}");
+        log::debug!("{unk:#x}, {unk2:#x}");
 
         // Reading
         Ok(SaveFile {
@@ -1,10 +1,10 @@
-use std::{collections::{HashMap, HashSet, VecDeque}, fs::read, hash::Hasher, io::{Read, Seek}};
+use std::{collections::{BTreeSet, HashMap, HashSet, VecDeque}, io::{Read, Seek}};
 
 use fasthash::FastHash;
 use num_enum::TryFromPrimitive;
 use serde::Serialize;
 
-use crate::{align::{align_up, seek_align_up}, reerr::{FileParseError, Result}, rsz::{dump::{enum_map, RszDump, RszField}, rszserde::{DeRsz, DeRszInstance, Object, RszFieldsValue, StringU16, StructData}}};
+use crate::{align::seek_align_up, reerr::Result, rsz::{dump::{enum_map, RszDump, RszField}, RszHeader, rszserde::{DeRsz, DeRszInstance, Object, RszFieldsValue, StringU16, StructData}}};
 use crate::file::*;
 
 #[repr(i32)]
@@ -96,10 +96,30 @@ impl StructRW for Class {
     }
 }
 
+impl Class {
+    /// Collects the type hashes and field name hashes of this class and every class inside it
+    pub fn hashes(&self, types: &mut BTreeSet<u32>, fields: &mut BTreeSet<u32>) {
+        types.insert(self.hash);
+        for (name_hash, value) in &self.fields {
+            fields.insert(*name_hash);
+            if let Some(class) = value.as_any().downcast_ref::<Class>() {
+                class.hashes(types, fields);
+            } else if let Some(array) = value.as_any().downcast_ref::<Array>() {
+                for class in array.values.iter().filter_map(|v| v.as_any().downcast_ref::<Class>()) {
+                    class.hashes(types, fields);
+                }
+            }
+        }
+    }
+}
+
 impl DeRszInstance for Class {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
     fn to_json(&self, ctx: &crate::rsz::rszserde::RszJsonSerializerCtx) -> serde_json::Value {
         todo!()
     }
@@ -149,6 +169,9 @@ impl DeRszInstance for Array {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
     fn to_json(&self, ctx: &crate::rsz::rszserde::RszJsonSerializerCtx) -> serde_json::Value {
 
         todo!()
@@ -271,6 +294,7 @@ pub fn to_dersz(object: Class) -> Result<DeRsz> {
     //println!("{structs:?}");
     Ok(DeRsz {
         offset,
+        version: RszHeader::VERSION,
         roots,
         structs,
         extern_idxs
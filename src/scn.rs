@@ -1,7 +1,7 @@
 #![allow(unused)]
 use crate::file_ext::*;
 use crate::rsz::Rsz;
-use crate::reerr::{Result, FileParseError::*};
+use crate::reerr::{Result, ReError};
 use std::io::{Read, Seek};
 use nalgebra_glm::Vec4;
 use serde::Serialize;
@@ -38,12 +38,8 @@ impl Scn {
     // thanks to mhrice for the structure
     pub fn new<F: Read + Seek>(mut file: F) -> Result<Scn> {
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?;
         if &magic != b"SCN\0" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("SCN"), 
-                read_magic: ext.to_string()
-            }))
+            return Err(Box::new(ReError::magic(b"SCN\0", &magic).at(0)))
         }
 
         let game_obj_count = file.read_u32()?;
@@ -0,0 +1,239 @@
+use std::{cmp::Ordering, fmt, path::PathBuf, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{reerr::{ErrorKind, Result}, resource::Resource, rsz::{dump::RszDump, query::{as_f64, as_string}, rszserde::DeRsz}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// The string contains the value
+    Contains,
+}
+
+impl Op {
+    // two character operators first so `>=` isn't read as `>`
+    const SYMBOLS: [(&'static str, Op); 8] = [
+        ("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge),
+        ("~", Op::Contains), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt),
+    ];
+
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq | Op::Contains => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on a field of an object, like `_Attack>200` or `_Param._Name~Sword`
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub path: String,
+    pub op: Op,
+    pub value: String,
+}
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (start, symbol, op) = Op::SYMBOLS.iter()
+            .filter_map(|(symbol, op)| s.find(symbol).map(|start| (start, *symbol, *op)))
+            .min_by_key(|(start, symbol, _)| (*start, usize::MAX - symbol.len()))
+            .ok_or_else(|| format!("no operator in {s:?}, use one of == != < <= > >= ~"))?;
+        let path = s[..start].trim();
+        if path.is_empty() {
+            return Err(format!("no field in {s:?}"))
+        }
+        Ok(Self { path: path.to_string(), op, value: s[start + symbol.len()..].trim().to_string() })
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = Op::SYMBOLS.iter().find(|(_, op)| *op == self.op).map(|(symbol, _)| *symbol).unwrap_or("?");
+        write!(f, "{}{symbol}{}", self.path, self.value)
+    }
+}
+
+impl Predicate {
+    /// None if the object has no such field
+    fn eval(&self, dersz: &DeRsz, object: u32) -> Option<(bool, serde_json::Value)> {
+        let value = dersz.get_field(object, &self.path).ok()?;
+        if let Some(n) = as_f64(value) {
+            let matched = match self.value.parse::<f64>() {
+                Ok(expected) if self.op != Op::Contains => n.partial_cmp(&expected).is_some_and(|ord| self.op.test(ord)),
+                _ => false,
+            };
+            // whole numbers are written without the .0
+            let value = if n.fract() == 0.0 && n.abs() < (1u64 << 53) as f64 { serde_json::json!(n as i64) } else { serde_json::json!(n) };
+            return Some((matched, value))
+        }
+        let s = as_string(value)?;
+        let matched = match self.op {
+            Op::Contains => s.contains(&self.value),
+            op => op.test(s.as_str().cmp(&self.value)),
+        };
+        Some((matched, serde_json::json!(s)))
+    }
+}
+
+/// Objects to look for: a type, fields they have to match, or both
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub type_name: Option<String>,
+    pub predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchMatch {
+    /// Index of the rsz block, only files like pog have more than one
+    pub rsz: usize,
+    pub object: u32,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// Path from a root, None if no root leads to the object
+    pub path: Option<String>,
+    /// Value of every field the predicates looked at
+    pub values: serde_json::Map<String, serde_json::Value>,
+}
+
+impl fmt::Display for SearchMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rsz != 0 {
+            write!(f, "rsz {} ", self.rsz)?;
+        }
+        match &self.path {
+            Some(path) => write!(f, "{path} {}", self.type_name)?,
+            None => write!(f, "object[{}] {}", self.object, self.type_name)?,
+        }
+        for (name, value) in &self.values {
+            write!(f, " {name}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Query {
+    pub fn search(&self, resource: &Resource) -> Result<Vec<SearchMatch>> {
+        let hash = match &self.type_name {
+            Some(name) => Some(*RszDump::name_map().get(name)
+                .ok_or_else(|| ErrorKind::InvalidValue(format!("Unknown type {name}")))?),
+            None => None,
+        };
+        let mut matches = Vec::new();
+        for (i, rsz) in resource.rszs().into_iter().enumerate() {
+            // most files don't have the type, skip them before deserializing
+            if hash.is_some_and(|hash| !rsz.type_descriptors.iter().any(|td| td.hash == hash)) {
+                continue
+            }
+            let dersz = rsz.deserialize_to_dersz()?;
+            let objects: Vec<u32> = match &self.type_name {
+                Some(name) => dersz.objects_of_type(name).collect(),
+                None => (1..dersz.structs.len() as u32).filter(|i| !dersz.extern_idxs.contains(i)).collect(),
+            };
+            for object in objects {
+                if let Some(values) = self.eval(&dersz, object) {
+                    matches.push(SearchMatch {
+                        rsz: i,
+                        object,
+                        type_name: dersz.type_name(object).unwrap_or_default().to_string(),
+                        path: dersz.path_of(object),
+                        values,
+                    });
+                }
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Values of the fields of the predicates if the object matches all of them
+    fn eval(&self, dersz: &DeRsz, object: u32) -> Option<serde_json::Map<String, serde_json::Value>> {
+        let mut values = serde_json::Map::new();
+        for predicate in &self.predicates {
+            let (matched, value) = predicate.eval(dersz, object)?;
+            if !matched {
+                return None
+            }
+            values.insert(predicate.path.clone(), value);
+        }
+        Some(values)
+    }
+}
+
+/// Matches of every searched file, the error is a string so files can be searched in parallel
+#[derive(Debug, Default)]
+pub struct SearchReport {
+    pub files: Vec<(PathBuf, std::result::Result<Vec<SearchMatch>, String>)>,
+}
+
+impl SearchReport {
+    pub fn matches(&self) -> usize {
+        self.files.iter().filter_map(|(_, result)| result.as_ref().ok()).map(Vec::len).sum()
+    }
+
+    pub fn matched_files(&self) -> usize {
+        self.files.iter().filter(|(_, result)| result.as_ref().is_ok_and(|matches| !matches.is_empty())).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|(_, result)| result.is_err()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> (String, Op, String) {
+        let predicate: Predicate = s.parse().unwrap();
+        (predicate.path, predicate.op, predicate.value)
+    }
+
+    #[test]
+    fn two_character_operators() {
+        assert_eq!(parse("_Attack>=200"), ("_Attack".to_string(), Op::Ge, "200".to_string()));
+        assert_eq!(parse("_Attack>200"), ("_Attack".to_string(), Op::Gt, "200".to_string()));
+        assert_eq!(parse("_Attack<=200"), ("_Attack".to_string(), Op::Le, "200".to_string()));
+        assert_eq!(parse("_Attack!=200"), ("_Attack".to_string(), Op::Ne, "200".to_string()));
+        assert_eq!(parse("_Attack==200"), ("_Attack".to_string(), Op::Eq, "200".to_string()));
+        assert_eq!(parse("_Attack=200"), ("_Attack".to_string(), Op::Eq, "200".to_string()));
+    }
+
+    #[test]
+    fn first_operator_wins() {
+        // the value may contain operators itself
+        assert_eq!(parse("_Param._Name~a>=b"), ("_Param._Name".to_string(), Op::Contains, "a>=b".to_string()));
+        assert_eq!(parse(" _Name = x=y "), ("_Name".to_string(), Op::Eq, "x=y".to_string()));
+    }
+
+    #[test]
+    fn invalid_predicates() {
+        assert!("_Attack".parse::<Predicate>().is_err());
+        assert!(">=200".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["_Attack>=200", "_Attack>200", "_Name~Sword", "_Id!=3"] {
+            assert_eq!(s.parse::<Predicate>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn op_ordering() {
+        assert!(Op::Ge.test(Ordering::Equal) && Op::Ge.test(Ordering::Greater) && !Op::Ge.test(Ordering::Less));
+        assert!(!Op::Gt.test(Ordering::Equal) && Op::Gt.test(Ordering::Greater));
+        assert!(Op::Le.test(Ordering::Equal) && !Op::Lt.test(Ordering::Equal));
+    }
+}
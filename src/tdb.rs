@@ -1,11 +1,8 @@
-use std::{
-    fs::File,
-    io::{Cursor, Read}
-};
+use std::io::{Cursor, Read};
 use bitfield::bitfield;
 use file_macros::StructRW;
 
-use crate::{reerr::{self, FileParseError, Result}, file::StructRW};
+use crate::{reerr::Result, file::StructRW};
 #[repr(C)]
 #[derive(Debug, file_macros::StructRW)]
 pub struct TDBHeader {
@@ -177,7 +174,7 @@ unsafe fn c_str_to_str<'a>(ptr: *const c_char) -> &'a str {
 }
 
 impl TDBHeader {
-    pub fn from_exe(file: &mut File) -> Result<Self> {
+    pub fn from_exe<R: Read>(file: &mut R) -> Result<Self> {
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
         let pattern = "TDB\0".as_bytes();
@@ -1,7 +1,9 @@
 use libdeflater::TileStream;
 
 use crate::bitfield::BitField;
-use crate::reerr::FileParseError::{self, MagicError};
+use crate::format::FileFormat;
+use crate::reerr::{ErrorKind, ReError};
+use crate::version::{Layout, VersionEntry};
 use crate::file_ext::*;
 use crate::compression::{
     Bc1Unorm, Bc3Unorm, Bc4Unorm, Bc5Unorm, Bc7Unorm, CompressionType, R8G8B8A8Unorm, R8G8Unorm, R8Unorm, TexCodec
@@ -9,7 +11,6 @@ use crate::compression::{
 
 use std::error::Error;
 use std::fmt;
-use std::str;
 use std::result::Result;
 use std::io::{Cursor, Read, Seek, SeekFrom};
 
@@ -21,6 +22,7 @@ pub struct RGBAImage {
 
 #[derive(Debug, Clone)]
 pub struct Tex {
+    version: u32,
     width: u32,
     height: u32,
     format: u32,
@@ -51,14 +53,13 @@ impl fmt::Display for TexInfo {
 impl Tex {
     pub fn new<F: Read + Seek>(mut file: F) -> std::result::Result<Tex, Box<dyn Error>> {
         let magic = file.read_magic()?;
-        let ext = str::from_utf8(&magic)?;
-        if ext != "TEX\0" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("TEX"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"TEX\0" {
+            return Err(Box::new(ReError::magic(b"TEX\0", &magic).at(0)))
         }
         let version = file.read_u32()?;
+        let Layout::Tex { swizzle, gdeflate } = VersionEntry::layout(FileFormat::Tex, version).map_err(|e| ReError::from_boxed(e).at(4))? else {
+            unreachable!("tex versions have a tex layout")
+        };
 
         let width = file.read_u16()?;
         let height = file.read_u16()?;
@@ -76,7 +77,7 @@ impl Tex {
         let _null1 = file.read_u16()?;
 
         // no idea where this is used, it's just zero?
-        if version > 27 && version != 190820018 {
+        if swizzle {
             let _swizzle_height_depth = file.read_u8()?;
             let _swizzle_width = file.read_u8()?;
             let _null2 = file.read_u16()?;
@@ -114,7 +115,7 @@ impl Tex {
         }
 
         let mut total_size = 0;
-        let gdef_sections = if version == 240701001 || version == 241106027{
+        let gdef_sections = if gdeflate {
             let sections = (0..mipmap_count * tex_count)
                 .into_iter()
                 .map(|_| {
@@ -161,7 +162,7 @@ impl Tex {
                 let mut out_buf: Vec<u8> = Vec::new();
                 out_buf.resize(out_size as usize, 0);
                 if in_size > out_size {
-                    return Err(Box::new(FileParseError::TexReadError{source: format!("in_size {in_size} larger than out_size {out_size}")}))
+                    return Err(Box::new(ReError::new(ErrorKind::TexRead(format!("in_size {in_size} larger than out_size {out_size}"))).at(tex_info.offset as u64)))
                 }
                 match libdeflater::GDeflateDecompressor::gdeflate_decompress(&in_buf, &mut out_buf)
                 {
@@ -169,17 +170,18 @@ impl Tex {
                         bytes_read += x as u32;
                         Ok(out_buf)
                     }
-                    Err(e) => Err(Box::new(FileParseError::GDeflateError { source: e.to_string()})),
+                    Err(e) => Err(Box::new(ReError::new(ErrorKind::GDeflate(e.to_string())).at(tex_info.offset as u64))),
                 }
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        println!("read {bytes_read}, decompressed {decompressed_size}");
+        log::debug!("read {bytes_read}, decompressed {decompressed_size}");
         if bytes_read > decompressed_size {
-            return Err(Box::new(FileParseError::TexReadError{source: String::from("Decompressed not same as btyes read")}))
+            return Err(ErrorKind::TexRead(String::from("Decompressed not same as btyes read")).into())
         }
 
         let tex = Tex {
+            version,
             width: width as u32,
             height: height as u32,
             format,
@@ -192,6 +194,62 @@ impl Tex {
         Ok(tex)
     }
 
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+
+    pub fn mip_count(&self) -> usize {
+        self.mip_count
+    }
+
+    /// Takes the top mips from the streaming copy of the texture, which has it at full resolution
+    pub fn merge_streaming(&mut self, streaming: Tex) -> Result<(), Box<dyn Error>> {
+        if streaming.width <= self.width {
+            log::debug!("streaming texture is {}x{}, not larger than {}x{}", streaming.width, streaming.height, self.width, self.height);
+            return Ok(())
+        }
+        // mips halve the size, so the streaming copy has this many levels above our first one
+        let extra = (streaming.width / self.width.max(1)).trailing_zeros() as usize;
+        if streaming.mip_count < extra || streaming._tex_count != self._tex_count {
+            return Err(ErrorKind::TexRead(format!(
+                "streaming texture has {} textures with {} mips, need {} textures with {extra} mips",
+                streaming._tex_count, streaming.mip_count, self._tex_count
+            )).into())
+        }
+        let mip_count = extra + self.mip_count;
+        let mut tex_infos = Vec::with_capacity(self._tex_count * mip_count);
+        let mut textures = Vec::with_capacity(self._tex_count * mip_count);
+        for tex_idx in 0..self._tex_count {
+            for mip_idx in 0..mip_count {
+                let (tex, i) = if mip_idx < extra {
+                    (&streaming, tex_idx * streaming.mip_count + mip_idx)
+                } else {
+                    (&*self, tex_idx * self.mip_count + mip_idx - extra)
+                };
+                tex_infos.push(tex.tex_infos[i].clone());
+                textures.push(tex.textures[i].clone());
+            }
+        }
+        self.width = streaming.width;
+        self.height = streaming.height;
+        self.mip_count = mip_count;
+        self.tex_infos = tex_infos;
+        self.textures = textures;
+        Ok(())
+    }
+
     pub fn to_rgba(&self, tex_idx: usize, mip_idx: usize) -> Result<RGBAImage, Box<dyn Error>> {
         let texture = &self.textures[tex_idx * self.mip_count + mip_idx];
         let tex_info = &self.tex_infos[tex_idx * self.mip_count + mip_idx];
@@ -200,7 +258,7 @@ impl Tex {
 
         let bpps = CompressionType::get_bpps(self.format);
         if bpps == 0 {
-                return Err(Box::new(FileParseError::Unsupported { source: format!("unsupported format {:02X}", self.format) }))
+                return Err(ErrorKind::UnsupportedTexFormat(self.format).into())
         }
         let texel_size = if bpps == 4 || bpps == 8 {
             4
@@ -270,7 +328,7 @@ impl Tex {
             0x62 | 0x63  => Bc7Unorm::decode_image( &texture, width as usize, height as usize, self.layout, writer,),
             x => {
                 eprintln!("unsupported format {:08X}", x);
-                return Err(Box::new(FileParseError::Unsupported { source: format!("unsupported format {x:08X}") }))
+                return Err(ErrorKind::UnsupportedTexFormat(x).into())
             }
         };
 
@@ -5,8 +5,8 @@ use serde::Deserialize;
 use serde::Serialize;
 
 use crate::file_ext::*;
-use crate::rsz::{Rsz, rszserde::{DeRsz, DeRszRegistry}};
-use crate::reerr::{Result, FileParseError::*};
+use crate::rsz::{utf16z, Rsz, rszserde::{DeRsz, DeRszRegistry}};
+use crate::reerr::{ErrorKind, Result, ReError};
 use std::io::Write;
 use std::io::{Read, Seek};
 
@@ -17,34 +17,74 @@ pub struct UserChild {
     pub name: String,
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
-pub struct User {
-    pub resource_names: Vec<String>,
-    pub children: Vec<UserChild>,
-    pub rsz: Rsz,
+/// The header of a user file, the same in every version, the version of the rsz block after it is what changes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct UserHeader {
+    pub resource_count: u32,
+    pub child_count: u32,
+    pub resource_list_offset: u64,
+    pub child_list_offset: u64,
+    pub rsz_offset: u64,
+    /// End of the rsz block, 0 if it goes to the end of the file
+    pub rsz_offset_cap: u64,
 }
 
-impl User {
-    pub fn new<F: Read + Seek>(mut file: F) -> Result<User> {
+impl UserHeader {
+    pub const SIZE: usize = 0x30;
+
+    pub fn read<F: Read + Seek>(file: &mut F) -> Result<Self> {
         let magic = file.read_magic()?;
-        let ext = core::str::from_utf8(&magic)?; if ext != "USR\0" {
-            return Err(Box::new(MagicError { 
-                real_magic: String::from("USR💖"), 
-                read_magic: ext.to_string()
-            }))
+        if &magic != b"USR\0" {
+            return Err(Box::new(ReError::magic(b"USR\0", &magic).at(0)))
         }
 
         let resource_count = file.read_u32()?;
         let child_count = file.read_u32()?;
         let padding = file.read_u32()?;
         if padding != 0 {
-            return Err(format!("Unexpected non-zero padding A: {}", padding).into());
+            return Err(Box::new(ReError::new(ErrorKind::NonZeroPadding { what: "USR header", value: padding as u64 }).at(0xc)));
         }
-        let resource_list_offset = file.read_u64()?;
-        let child_list_offset = file.read_u64()?;
-        let rsz_offset = file.read_u64()?;
-        let rsz_offset_cap = file.read_u64()?;
+        Ok(Self {
+            resource_count,
+            child_count,
+            resource_list_offset: file.read_u64()?,
+            child_list_offset: file.read_u64()?,
+            rsz_offset: file.read_u64()?,
+            rsz_offset_cap: file.read_u64()?,
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(b"USR\0")?;
+        writer.write_u32::<LittleEndian>(self.resource_count)?;
+        writer.write_u32::<LittleEndian>(self.child_count)?;
+        writer.write_all(&[0; 4])?;
+        writer.write_u64::<LittleEndian>(self.resource_list_offset)?;
+        writer.write_u64::<LittleEndian>(self.child_list_offset)?;
+        writer.write_u64::<LittleEndian>(self.rsz_offset)?;
+        writer.write_u64::<LittleEndian>(self.rsz_offset_cap)?;
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct User {
+    pub resource_names: Vec<String>,
+    pub children: Vec<UserChild>,
+    pub rsz: Rsz,
+}
+
+impl User {
+    pub fn new<F: Read + Seek>(mut file: F) -> Result<User> {
+        let UserHeader {
+            resource_count,
+            child_count,
+            resource_list_offset,
+            child_list_offset,
+            rsz_offset,
+            rsz_offset_cap,
+        } = UserHeader::read(&mut file)?;
 
         file.seek_assert_align_up(resource_list_offset, 16)?;
         let resource_name_offsets = (0..resource_count)
@@ -57,7 +97,7 @@ impl User {
                 let hash = file.read_u32()?;
                 let padding = file.read_u32()?;
                 if padding != 0 {
-                    return Err(format!("Child Unexpected non-zero padding A: {}", padding).into());
+                    return Err(ReError::new(ErrorKind::NonZeroPadding { what: "USR child", value: padding as u64 }).at(file.tell()? - 4).into());
                 }
                 let name_offset = file.read_u64()?;
                 Ok((hash, name_offset))
@@ -70,7 +110,7 @@ impl User {
                 file.seek_noop(resource_name_offset)?;
                 let name = file.read_u16str()?;
                 if name.ends_with(".user") {
-                    return Err("USER resource".into());
+                    return Err(ReError::new(ErrorKind::InvalidValue(format!("USER resource {name}"))).at(resource_name_offset).into());
                 }
                 Ok(name)
             })
@@ -82,7 +122,7 @@ impl User {
                 file.seek_noop(name_offset)?;
                 let name = file.read_u16str()?;
                 if !name.ends_with(".user") {
-                    return Err("Non-USER child".into());
+                    return Err(ReError::new(ErrorKind::InvalidValue(format!("Non-USER child {name}"))).at(name_offset).into());
                 }
                 Ok(UserChild { hash, name })
             })
@@ -99,44 +139,71 @@ impl User {
     }
     
     pub fn to_buf(&self) -> Result<Vec<u8>> {
+        let align = |offset: usize| offset.div_ceil(16) * 16;
+        let resource_list_offset = UserHeader::SIZE;
+        let child_list_offset = align(resource_list_offset + self.resource_names.len() * size_of::<u64>());
+        // resource names then child names, each list and the rsz start on 16 bytes
+        let resource_strings: Vec<Vec<u8>> = self.resource_names.iter().map(|name| utf16z(name)).collect();
+        let child_strings: Vec<Vec<u8>> = self.children.iter().map(|child| utf16z(&child.name)).collect();
+        let strings_offset = align(child_list_offset + self.children.len() * (size_of::<u32>() * 2 + size_of::<u64>()));
+        let strings_len: usize = resource_strings.iter().chain(&child_strings).map(Vec::len).sum();
+        let rsz_offset = align(strings_offset + strings_len);
 
         let mut buf = vec![];
-        buf.write_all(b"USR\0")?;
-        buf.write_u32::<LittleEndian>(self.resource_names.len() as u32)?;
-        buf.write_u32::<LittleEndian>(self.children.len() as u32)?;
-        buf.write_all(&[0; 4])?;
-        let resource_list_offset = buf.len() + size_of::<u64>() * 4;
-        // this has to be aligned by 16 likely
-        let child_list_offset = resource_list_offset + self.resource_names.len() * size_of::<u64>();
-        // this has to be aligned by 16 likely
-        let rsz_offset = child_list_offset + self.children.len() * (size_of::<u32>() * 2 + size_of::<u64>());
-        let rsz_buf = self.rsz.to_buf(rsz_offset)?;
-        buf.write_u64::<LittleEndian>(resource_list_offset as u64)?;
-        buf.write_u64::<LittleEndian>(child_list_offset as u64)?;
-        buf.write_u64::<LittleEndian>(rsz_offset as u64)?;
-        buf.write_u64::<LittleEndian>(0)?;
-        //buf.write_u64::<LittleEndian>(rsz_offset_cap as u64)?;
-        for child in &self.children {
+        UserHeader {
+            resource_count: self.resource_names.len() as u32,
+            child_count: self.children.len() as u32,
+            resource_list_offset: resource_list_offset as u64,
+            child_list_offset: child_list_offset as u64,
+            rsz_offset: rsz_offset as u64,
+            rsz_offset_cap: 0,
+        }.write(&mut buf)?;
+
+        let mut string_offset = strings_offset;
+        for name in &resource_strings {
+            buf.write_u64::<LittleEndian>(string_offset as u64)?;
+            string_offset += name.len();
+        }
+        buf.resize(child_list_offset, 0);
+        for (child, name) in self.children.iter().zip(&child_strings) {
             buf.write_u32::<LittleEndian>(child.hash)?;
             buf.write_all(&[0; 4])?;
-            buf.write_u64::<LittleEndian>(0)?;
-            // figure out where to put the string tables
+            buf.write_u64::<LittleEndian>(string_offset as u64)?;
+            string_offset += name.len();
         }
-
-        buf.extend(rsz_buf);
+        buf.resize(strings_offset, 0);
+        for name in resource_strings.iter().chain(&child_strings) {
+            buf.write_all(name)?;
+        }
+        buf.resize(rsz_offset, 0);
+        buf.extend(self.rsz.to_buf(rsz_offset)?);
         Ok(buf)
     }
+
     pub fn from_json_file(file: &str) -> Result<User> {
-        let data = std::fs::read_to_string(file)?;
-        let json_data: serde_json::Value = serde_json::from_str(&data).unwrap();
-        let rsz_json = json_data.get("rsz").unwrap();
+        Self::from_json(&std::fs::read_to_string(file)?)
+    }
+
+    pub fn from_json(data: &str) -> Result<User> {
+        let json_data: serde_json::Value = serde_json::from_str(data)?;
+        let rsz_json = json_data.get("rsz")
+            .ok_or_else(|| ErrorKind::InvalidValue("user json has no rsz".to_string()))?;
         let mut registry = DeRszRegistry::new();
         registry.init();
         let dersz: DeRsz = DeRsz::from_json(rsz_json, registry.into())?; 
         let rsz = Result::<Rsz>::from(dersz)?;
+        // hand written json may leave these out
+        let resource_names = match json_data.get("resource_names") {
+            Some(names) => serde_json::from_value(names.clone())?,
+            None => vec![],
+        };
+        let children = match json_data.get("children") {
+            Some(children) => serde_json::from_value(children.clone())?,
+            None => vec![],
+        };
         Ok(User {
-            resource_names: vec![],
-            children: vec![],
+            resource_names,
+            children,
             rsz
         })
     }
@@ -158,3 +225,76 @@ impl Serialize for User {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::rsz::RszHeader;
+
+    use super::*;
+
+    /// A user file with no resources or children around an rsz block with no roots
+    fn file(rsz_version: u32) -> Vec<u8> {
+        let mut buf = vec![];
+        UserHeader {
+            resource_list_offset: 0x30,
+            child_list_offset: 0x30,
+            rsz_offset: 0x30,
+            ..Default::default()
+        }.write(&mut buf).unwrap();
+        let type_descriptor_offset = RszHeader::size(rsz_version).unwrap() as u64;
+        let data_offset = (type_descriptor_offset + 8).div_ceil(16) * 16;
+        RszHeader {
+            version: rsz_version,
+            type_descriptor_count: 1,
+            type_descriptor_offset,
+            data_offset,
+            extern_offset: data_offset,
+            ..Default::default()
+        }.write(&mut buf).unwrap();
+        buf.resize(0x30 + data_offset as usize, 0);
+        buf
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = UserHeader {
+            resource_count: 1,
+            child_count: 2,
+            resource_list_offset: 0x30,
+            child_list_offset: 0x40,
+            rsz_offset: 0x80,
+            rsz_offset_cap: 0,
+        };
+        let mut buf = vec![];
+        header.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), UserHeader::SIZE);
+        assert_eq!(UserHeader::read(&mut Cursor::new(&buf)).unwrap(), header);
+    }
+
+    #[test]
+    fn header_padding() {
+        let mut buf = file(0x10);
+        buf[0xc] = 1;
+        assert!(UserHeader::read(&mut Cursor::new(&buf)).is_err());
+    }
+
+    #[test]
+    fn keeps_rsz_version() {
+        for version in [0x10, 3] {
+            let buf = file(version);
+            let user = User::new(Cursor::new(&buf)).unwrap();
+            assert_eq!(user.rsz.version(), version);
+            let rebuilt = user.to_buf().unwrap();
+            // reading without a cap pads the rsz data
+            assert_eq!(rebuilt[..buf.len()], buf[..]);
+        }
+    }
+
+    #[test]
+    fn json_without_rsz() {
+        let e = User::from_json(r#"{"resource_names": []}"#).unwrap_err();
+        assert_eq!(ReError::find(e.as_ref()).map(|e| e.category()), Some("invalid_value"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, fmt, io::Cursor, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{format::FileFormat, reerr::Result, resource::{build_from_json, OutputStyle, Resource}, rsz::Rsz, user::User};
+
+/// What the first differing byte of a rebuilt file belongs to
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Location {
+    /// Header, resource and child tables of the user file
+    UserHeader,
+    /// Header, type descriptors and extern slots of the rsz block
+    RszHeader,
+    Field { object: u32, type_name: String, field: String },
+    /// One file ends before the other
+    End,
+}
+
+impl Location {
+    /// Name failures are grouped by in the summary
+    pub fn group(&self) -> &str {
+        match self {
+            Self::UserHeader => "<user header>",
+            Self::RszHeader => "<rsz header>",
+            Self::Field { type_name, .. } => type_name,
+            Self::End => "<size>",
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UserHeader => write!(f, "user header"),
+            Self::RszHeader => write!(f, "rsz header"),
+            Self::Field { object, type_name, field } if field.is_empty() => write!(f, "object {object} {type_name}"),
+            Self::Field { object, type_name, field } => write!(f, "object {object} {type_name}.{field}"),
+            Self::End => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub offset: u64,
+    pub original_size: usize,
+    pub rebuilt_size: usize,
+    pub location: Location,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "first difference at {:#x} in {}", self.offset, self.location)?;
+        if self.original_size != self.rebuilt_size {
+            write!(f, ", size {:#x} rebuilt as {:#x}", self.original_size, self.rebuilt_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dumps a user file to json like `dump` does, builds it back like `build` does and compares the bytes.
+/// None if the rebuilt file is the same
+pub fn verify_user(data: &[u8], style: OutputStyle) -> Result<Option<Mismatch>> {
+    let resource = Resource::User(User::new(Cursor::new(data))?);
+    let mut json = Vec::new();
+    resource.write_export(&mut json, style)?;
+    let rebuilt = build_from_json(std::str::from_utf8(&json)?, FileFormat::User)?;
+
+    let Some(offset) = first_difference(data, &rebuilt) else {
+        return Ok(None)
+    };
+    let Resource::User(user) = &resource else {
+        unreachable!()
+    };
+    Ok(Some(Mismatch {
+        offset: offset as u64,
+        original_size: data.len(),
+        rebuilt_size: rebuilt.len(),
+        location: locate(&user.rsz, offset as u64, data.len())?,
+    }))
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(i) => Some(i),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+/// Maps an offset in the original file to the part of it that was read from there
+fn locate(rsz: &Rsz, offset: u64, size: usize) -> Result<Location> {
+    let base = rsz.offset() as u64;
+    let data_start = base + rsz.data_offset();
+    if offset >= size as u64 {
+        return Ok(Location::End)
+    }
+    if offset < base {
+        return Ok(Location::UserHeader)
+    }
+    if offset < data_start {
+        return Ok(Location::RszHeader)
+    }
+    let pos = offset - data_start;
+    let field = rsz.field_offsets()?.into_iter().take_while(|field| field.offset <= pos).last();
+    Ok(match field {
+        Some(field) => Location::Field { object: field.object, type_name: field.type_name, field: field.field },
+        None => Location::RszHeader,
+    })
+}
+
+/// Result of verifying many files, the error is a string so files can be verified in parallel
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub files: Vec<(PathBuf, std::result::Result<Option<Mismatch>, String>)>,
+}
+
+impl VerifyReport {
+    pub fn ok(&self) -> usize {
+        self.files.iter().filter(|(_, result)| matches!(result, Ok(None))).count()
+    }
+
+    pub fn mismatched(&self) -> usize {
+        self.files.iter().filter(|(_, result)| matches!(result, Ok(Some(_)))).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.files.iter().filter(|(_, result)| result.is_err()).count()
+    }
+
+    /// How many files differ first in each type
+    pub fn by_type(&self) -> BTreeMap<String, usize> {
+        let mut types = BTreeMap::new();
+        for (_, result) in &self.files {
+            if let Ok(Some(mismatch)) = result {
+                *types.entry(mismatch.location.group().to_string()).or_default() += 1;
+            }
+        }
+        types
+    }
+}
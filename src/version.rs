@@ -0,0 +1,121 @@
+use crate::{format::FileFormat, game::GameProfile, reerr::{ErrorKind, Result}};
+
+/// Version dependent layout of a file, for the parsers that need to know it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Default,
+    /// From version 28 the header has an extra swizzle block, wilds compresses the mips with gdeflate
+    Tex { swizzle: bool, gdeflate: bool },
+    /// Version 3 rsz blocks have no extern slot count, padding or extern offset in the header
+    Rsz { externs: bool },
+    /// Fields each game added to the mesh headers: sunbreak to the buffer header, sf6 and wilds to the main header
+    Mesh { sunbreak: bool, sf6: bool, wilds: bool },
+}
+
+/// A supported combination of file name suffix and header version, None matches anything
+#[derive(Debug)]
+pub struct VersionEntry {
+    pub format: FileFormat,
+    pub suffix: Option<u32>,
+    pub header: Option<u32>,
+    pub layout: Layout,
+}
+
+const fn entry(format: FileFormat, suffix: Option<u32>, header: Option<u32>, layout: Layout) -> VersionEntry {
+    VersionEntry { format, suffix, header, layout }
+}
+
+const TEX_OLD: Layout = Layout::Tex { swizzle: false, gdeflate: false };
+const TEX_SWIZZLE: Layout = Layout::Tex { swizzle: true, gdeflate: false };
+const TEX_GDEFLATE: Layout = Layout::Tex { swizzle: true, gdeflate: true };
+const RSZ_EXTERNS: Layout = Layout::Rsz { externs: true };
+const RSZ_NO_EXTERNS: Layout = Layout::Rsz { externs: false };
+
+const MESH_RISE: Layout = Layout::Mesh { sunbreak: false, sf6: false, wilds: false };
+const MESH_SUNBREAK: Layout = Layout::Mesh { sunbreak: true, sf6: false, wilds: false };
+const MESH_SF6: Layout = Layout::Mesh { sunbreak: true, sf6: true, wilds: false };
+const MESH_WILDS: Layout = Layout::Mesh { sunbreak: true, sf6: true, wilds: true };
+
+// The first entry of a format is the suffix writers use, so keep the newest first
+pub const WILDS: &[VersionEntry] = &[
+    entry(FileFormat::User, Some(3), None, Layout::Default),
+    entry(FileFormat::User, Some(2), None, Layout::Default),
+    entry(FileFormat::Scn, Some(20), None, Layout::Default),
+    entry(FileFormat::Pog, None, None, Layout::Default),
+    entry(FileFormat::PogList, None, None, Layout::Default),
+    entry(FileFormat::Rsz, None, Some(0x10), RSZ_EXTERNS),
+    entry(FileFormat::Rsz, None, Some(3), RSZ_NO_EXTERNS),
+    entry(FileFormat::Tex, Some(241106027), Some(241106027), TEX_GDEFLATE),
+    entry(FileFormat::Tex, Some(240701001), Some(240701001), TEX_GDEFLATE),
+    entry(FileFormat::Msg, Some(23), None, Layout::Default),
+    entry(FileFormat::Mesh, Some(241111606), Some(241111606), MESH_WILDS),
+    entry(FileFormat::Oft, None, None, Layout::Default),
+    entry(FileFormat::Save, None, None, Layout::Default),
+    entry(FileFormat::Exe, None, None, Layout::Default),
+];
+
+pub const SF6: &[VersionEntry] = &[
+    entry(FileFormat::User, Some(2), None, Layout::Default),
+    entry(FileFormat::Scn, Some(20), None, Layout::Default),
+    entry(FileFormat::Rsz, None, Some(0x10), RSZ_EXTERNS),
+    entry(FileFormat::Rsz, None, Some(3), RSZ_NO_EXTERNS),
+    entry(FileFormat::Tex, Some(36), Some(36), TEX_SWIZZLE),
+    entry(FileFormat::Tex, Some(35), Some(35), TEX_SWIZZLE),
+    entry(FileFormat::Tex, Some(34), Some(34), TEX_SWIZZLE),
+    entry(FileFormat::Tex, Some(30), Some(30), TEX_SWIZZLE),
+    entry(FileFormat::Mesh, Some(230110883), Some(230110883), MESH_SF6),
+];
+
+pub const RISE: &[VersionEntry] = &[
+    entry(FileFormat::User, Some(2), None, Layout::Default),
+    entry(FileFormat::Scn, Some(20), None, Layout::Default),
+    entry(FileFormat::Rsz, None, Some(0x10), RSZ_EXTERNS),
+    entry(FileFormat::Rsz, None, Some(3), RSZ_NO_EXTERNS),
+    entry(FileFormat::Tex, Some(28), Some(28), TEX_SWIZZLE),
+    entry(FileFormat::Tex, Some(190820018), Some(190820018), TEX_OLD),
+    entry(FileFormat::Tex, Some(10), Some(10), TEX_OLD),
+    entry(FileFormat::Mesh, Some(2109148288), Some(2109148288), MESH_SUNBREAK),
+    entry(FileFormat::Mesh, Some(2008058288), Some(2008058288), MESH_RISE),
+];
+
+/// Version tables by the `game` of a profile
+pub const GAMES: &[(&str, &[VersionEntry])] = &[
+    ("mhwilds", WILDS),
+    ("sf6", SF6),
+    ("mhrise", RISE),
+];
+
+/// The version table of a game
+pub fn versions_for(game: &str) -> Result<&'static [VersionEntry]> {
+    GAMES.iter()
+        .find(|(name, _)| *name == game)
+        .map(|(_, versions)| *versions)
+        .ok_or_else(|| {
+            let known = GAMES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+            format!("Unknown game {game}, the known games are {known}").into()
+        })
+}
+
+impl VersionEntry {
+    fn matches(&self, format: FileFormat, suffix: Option<u32>, header: Option<u32>) -> bool {
+        let matches = |expected: Option<u32>, found: Option<u32>| expected.is_none() || found.is_none() || expected == found;
+        self.format == format && matches(self.suffix, suffix) && matches(self.header, header)
+    }
+
+    /// Finds the entry for a file, a version that isn't known (None) is not checked
+    pub fn find(format: FileFormat, suffix: Option<u32>, header: Option<u32>) -> Result<&'static VersionEntry> {
+        GameProfile::active().versions.iter()
+            .find(|entry| entry.matches(format, suffix, header))
+            .ok_or(ErrorKind::UnsupportedFileVersion { format: format.extension(), suffix, header }.into())
+    }
+
+    /// Layout for a header version, for parsers that only have the reader
+    pub fn layout(format: FileFormat, header: u32) -> Result<Layout> {
+        Ok(Self::find(format, None, Some(header))?.layout)
+    }
+
+    /// The suffix new files of the format get, None if the format has no suffix
+    pub fn write_suffix(format: FileFormat) -> Option<u32> {
+        GameProfile::active().versions.iter().find(|entry| entry.format == format).and_then(|entry| entry.suffix)
+    }
+}
@@ -0,0 +1,253 @@
+use std::{fs::File, io::BufReader, path::{Path, PathBuf}};
+
+use crate::{pak::{Pak, PakKey, PathList}, reerr::Result, walk::{walk, WalkFilter}};
+
+/// Path inside a vfs, always with `/` separators
+pub fn vfs_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Somewhere files can be read from by their path inside the game, like `natives/STM/...`
+pub trait Vfs: Send + Sync {
+    /// Where the files come from, for messages
+    fn describe(&self) -> String;
+
+    fn contains(&self, path: &str) -> bool;
+
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// Files inside `dir` that pass the filter, or `dir` itself if it is a file
+    fn list(&self, dir: &str, filter: &WalkFilter) -> Result<Vec<String>>;
+
+    /// The file on disk, None if the file only exists inside an archive
+    fn disk_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Loose files in a directory, an empty root takes paths as they are
+pub struct DirVfs {
+    root: PathBuf,
+}
+
+impl DirVfs {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl Vfs for DirVfs {
+    fn describe(&self) -> String {
+        format!("{:?}", self.root)
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let full_path = self.root.join(path);
+        Ok(std::fs::read(&full_path).map_err(|e| format!("Could not read {full_path:?}: {e}"))?)
+    }
+
+    fn list(&self, dir: &str, filter: &WalkFilter) -> Result<Vec<String>> {
+        let full_path = self.root.join(dir);
+        if full_path.is_dir() {
+            // keep the walked paths relative to the root so the output path strips it
+            Ok(walk(&full_path, filter)?.into_iter().map(|file| vfs_path(&Path::new(dir).join(file))).collect())
+        } else if full_path.is_file() {
+            Ok(vec![dir.to_string()])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.root.join(path))
+    }
+}
+
+/// A pak, listing it needs a path list since it only stores hashes
+pub struct PakVfs {
+    path: PathBuf,
+    pak: Pak<BufReader<File>>,
+    paths: Option<Vec<String>>,
+}
+
+impl PakVfs {
+    pub fn open(path: &Path, key: Option<&PakKey>, paths: Option<&PathList>) -> Result<Self> {
+        let pak = Pak::open(path, key)?;
+        let paths = paths.map(|paths| pak.resolve(paths).into_iter().map(str::to_string).collect());
+        Ok(Self { path: path.to_path_buf(), pak, paths })
+    }
+
+    pub fn pak(&self) -> &Pak<BufReader<File>> {
+        &self.pak
+    }
+}
+
+impl Vfs for PakVfs {
+    fn describe(&self) -> String {
+        format!("{:?}", self.path)
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.pak.contains(path)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.pak.read(path)
+    }
+
+    fn list(&self, dir: &str, filter: &WalkFilter) -> Result<Vec<String>> {
+        if self.pak.contains(dir) {
+            return Ok(vec![dir.to_string()])
+        }
+        let Some(paths) = &self.paths else {
+            return Err("Need a path list to find files in a pak, give it with --pak-paths or in the profile".into())
+        };
+        let dir = dir.trim_end_matches('/');
+        Ok(paths.iter()
+            .filter(|path| match path.strip_prefix(dir) {
+                Some("") => true,
+                Some(rel) => rel.starts_with('/') && filter.accepts(Path::new(&rel[1..])),
+                None => dir.is_empty() && filter.accepts(Path::new(path)),
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// Backends stacked like the game loads them, a file in a later layer overrides the same file in the earlier ones
+#[derive(Default)]
+pub struct LayeredVfs {
+    layers: Vec<Box<dyn Vfs>>,
+}
+
+impl LayeredVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer above the others
+    pub fn push(&mut self, layer: Box<dyn Vfs>) {
+        self.layers.push(layer);
+    }
+
+    /// Opens every root in order, paks also get their `.patch_NNN.pak` files stacked above them.
+    /// No roots reads paths as they are
+    pub fn open(roots: &[PathBuf], key: Option<&PakKey>, paths: Option<&PathList>) -> Result<Self> {
+        let mut vfs = Self::new();
+        if roots.is_empty() {
+            vfs.push(Box::new(DirVfs::new(PathBuf::new())));
+        }
+        for root in roots {
+            if !root.is_file() {
+                vfs.push(Box::new(DirVfs::new(root.clone())));
+                continue
+            }
+            vfs.push(Box::new(PakVfs::open(root, key, paths)?));
+            for patch in patch_paks(root)? {
+                eprintln!("[INFO] Patch Pak {:?}", patch);
+                vfs.push(Box::new(PakVfs::open(&patch, key, paths)?));
+            }
+        }
+        Ok(vfs)
+    }
+
+    /// The top layer that has the file
+    pub fn layer_of(&self, path: &str) -> Option<&dyn Vfs> {
+        self.layers.iter().rev().find(|layer| layer.contains(path)).map(|layer| layer.as_ref())
+    }
+}
+
+/// `re_chunk_000.pak.patch_001.pak` and so on, in the order the game applies them
+fn patch_paks(pak: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (pak.parent(), pak.file_name()) else {
+        return Ok(Vec::new())
+    };
+    let prefix = format!("{}.patch_", name.to_string_lossy());
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let mut patches: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with(&prefix) && name.ends_with(".pak") && !name[prefix.len()..].contains(".pak.")
+        })
+        .map(|entry| pak.with_file_name(entry.file_name()))
+        .collect();
+    patches.sort();
+    Ok(patches)
+}
+
+impl Vfs for LayeredVfs {
+    fn describe(&self) -> String {
+        self.layers.iter().map(|layer| layer.describe()).collect::<Vec<_>>().join(" < ")
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.layer_of(path).is_some()
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        match self.layer_of(path) {
+            Some(layer) => layer.read(path),
+            None => Err(format!("{path} is not in {}", self.describe()).into()),
+        }
+    }
+
+    fn list(&self, dir: &str, filter: &WalkFilter) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        for layer in &self.layers {
+            files.extend(layer.list(dir, filter)?);
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    fn disk_path(&self, path: &str) -> Option<PathBuf> {
+        match self.layer_of(path) {
+            Some(layer) => layer.disk_path(path),
+            // missing files keep the path in the bottom layer so the error names it
+            None => self.layers.first().and_then(|layer| layer.disk_path(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pak::{Compression, PakWriter};
+
+    fn write_pak(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = PakWriter::new(Compression::None);
+        for (name, data) in files {
+            writer.add(name, data.to_vec());
+        }
+        writer.save(path).unwrap();
+    }
+
+    #[test]
+    fn later_layers_override() {
+        let dir = std::env::temp_dir().join(format!("mhtame_vfs_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let pak = dir.join("re_chunk_000.pak");
+        write_pak(&pak, &[("natives/STM/a.user.3", b"base"), ("natives/STM/b.user.3", b"base")]);
+        // written out of order, patches still apply by number
+        write_pak(&dir.join("re_chunk_000.pak.patch_002.pak"), &[("natives/STM/a.user.3", b"patch 2")]);
+        write_pak(&dir.join("re_chunk_000.pak.patch_001.pak"), &[("natives/STM/a.user.3", b"patch 1"), ("natives/STM/b.user.3", b"patch 1")]);
+        let loose = dir.join("loose");
+        std::fs::create_dir_all(loose.join("natives/STM")).unwrap();
+        std::fs::write(loose.join("natives/STM/b.user.3"), b"loose").unwrap();
+
+        let vfs = LayeredVfs::open(&[pak, loose.clone()], None, None).unwrap();
+        assert_eq!(vfs.read("natives/STM/a.user.3").unwrap(), b"patch 2");
+        assert_eq!(vfs.read("natives/STM/b.user.3").unwrap(), b"loose");
+        assert_eq!(vfs.disk_path("natives/STM/b.user.3"), Some(loose.join("natives/STM/b.user.3")));
+        assert_eq!(vfs.disk_path("natives/STM/a.user.3"), None);
+        assert!(vfs.layer_of("natives/STM/c.user.3").is_none());
+        assert!(vfs.read("natives/STM/c.user.3").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
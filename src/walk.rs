@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use crate::reerr::Result;
+
+/// Shell style glob, `*` and `?` stay inside a path component and `**` matches any number of them
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: Vec<char>,
+    match_name: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let pattern = pattern.replace('\\', "/");
+        Self {
+            // patterns without a separator match the file name anywhere in the tree, like find -name
+            match_name: !pattern.contains('/'),
+            pattern: pattern.trim_start_matches("./").chars().collect(),
+        }
+    }
+
+    /// `path` is relative to the directory being walked
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        let target = if self.match_name {
+            path.rsplit('/').next().unwrap_or(&path)
+        } else {
+            &path
+        };
+        let target: Vec<char> = target.chars().collect();
+        glob_match(&self.pattern, &target)
+    }
+}
+
+fn glob_match(pattern: &[char], s: &[char]) -> bool {
+    match pattern.first() {
+        None => s.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // a/**/b also matches a/b
+            if rest.first() == Some(&'/') && glob_match(&rest[1..], s) {
+                return true
+            }
+            (0..=s.len()).any(|i| glob_match(rest, &s[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=s.len() {
+                if glob_match(rest, &s[i..]) {
+                    return true
+                }
+                if s.get(i) == Some(&'/') {
+                    break
+                }
+            }
+            false
+        }
+        Some('?') => matches!(s.first(), Some(c) if *c != '/') && glob_match(&pattern[1..], &s[1..]),
+        Some(c) => s.first() == Some(c) && glob_match(&pattern[1..], &s[1..]),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkFilter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+    pub extensions: Vec<String>,
+}
+
+impl WalkFilter {
+    pub fn new(include: &[String], exclude: &[String], extensions: &[String]) -> Self {
+        Self {
+            include: include.iter().map(|g| Glob::new(g)).collect(),
+            exclude: exclude.iter().map(|g| Glob::new(g)).collect(),
+            extensions: extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect(),
+        }
+    }
+
+    fn excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|g| g.matches(path))
+    }
+
+    /// Extensions are compared against every part of the name, so `user` also takes `x.user.3`
+    fn has_extension(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true
+        }
+        let Some(name) = path.file_name() else {
+            return false
+        };
+        let name = name.to_string_lossy().to_lowercase();
+        name.split('.').skip(1).any(|ext| self.extensions.iter().any(|e| e == ext))
+    }
+
+    pub fn accepts(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.matches(path)))
+            && !self.excluded(path)
+            && self.has_extension(path)
+    }
+}
+
+/// Recursively lists the files in `dir` that pass the filter, the paths are relative to `dir` and sorted
+pub fn walk(dir: &Path, filter: &WalkFilter) -> Result<Vec<PathBuf>> {
+    let mut results = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel_dir) = dirs.pop() {
+        let entries = std::fs::read_dir(dir.join(&rel_dir))
+            .map_err(|e| format!("Could not read directory {:?}: {e}", dir.join(&rel_dir)))?;
+        for entry in entries {
+            let entry = entry?;
+            let rel_path = rel_dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                // excluded directories are not walked at all
+                if !filter.excluded(&rel_path) {
+                    dirs.push(rel_path);
+                }
+            } else if filter.accepts(&rel_path) {
+                results.push(rel_path);
+            }
+        }
+    }
+    results.sort();
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).matches(Path::new(path))
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(matches("*.user.3", "natives/STM/a.user.3"));
+        assert!(matches("natives/*/a.user.3", "natives/STM/a.user.3"));
+        assert!(!matches("natives/*", "natives/STM/a.user.3"));
+        assert!(matches("natives/**/a.user.3", "natives/STM/GameDesign/a.user.3"));
+        assert!(matches("natives/**/a.user.3", "natives/a.user.3"));
+        assert!(matches("./natives/STM/?.user.3", "natives/STM/a.user.3"));
+        assert!(!matches("natives/STM?a.user.3", "natives/STM/a.user.3"));
+    }
+
+    #[test]
+    fn filter() {
+        let filter = WalkFilter::new(&["natives/**".to_string()], &["*Test*".to_string()], &[".USER".to_string()]);
+        assert!(filter.accepts(Path::new("natives/STM/a.user.3")));
+        assert!(!filter.accepts(Path::new("natives/STM/ItemTest.user.3")));
+        assert!(!filter.accepts(Path::new("natives/STM/a.tex.241106027")));
+        assert!(!filter.accepts(Path::new("other/a.user.3")));
+        assert!(WalkFilter::default().accepts(Path::new("anything")));
+    }
+
+    #[test]
+    fn walk_skips_excluded_directories() {
+        let dir = std::env::temp_dir().join(format!("mhtame_walk_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in ["a/x.user.3", "a/b/y.user.3", "skip/z.user.3", "a/w.tex.241106027"] {
+            std::fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        let files = walk(&dir, &WalkFilter::new(&[], &["skip".to_string()], &["user".to_string()])).unwrap();
+        assert_eq!(files, [PathBuf::from("a/b/y.user.3"), PathBuf::from("a/x.user.3")]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}